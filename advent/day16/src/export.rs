@@ -0,0 +1,31 @@
+use crate::printgrid::{heatmap_pixels, GridElement};
+use array2d::Array2D;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+pub fn write_ppm(grid: &Array2D<GridElement>, file_name: &str, scale: usize) -> io::Result<()> {
+    let (wid, hei, data) = heatmap_pixels(grid, scale);
+    let mut file = BufWriter::new(File::create(file_name)?);
+    write!(file, "P6\n{} {}\n255\n", wid, hei)?;
+    file.write_all(&data)?;
+    file.flush()
+}
+
+pub fn write_png(grid: &Array2D<GridElement>, file_name: &str, scale: usize) -> io::Result<()> {
+    let (wid, hei, data) = heatmap_pixels(grid, scale);
+    let file = BufWriter::new(File::create(file_name)?);
+    let mut encoder = png::Encoder::new(file, wid as u32, hei as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)
+}
+
+/// Picks the format from the file extension, defaults to ppm
+pub fn write_image(grid: &Array2D<GridElement>, file_name: &str, scale: usize) -> io::Result<()> {
+    if file_name.ends_with(".png") {
+        write_png(grid, file_name, scale)
+    } else {
+        write_ppm(grid, file_name, scale)
+    }
+}
@@ -1,11 +1,13 @@
 #![allow(dead_code)]
-use crate::printgrid::{printgrid, GridElement};
+use crate::export::write_image;
+use crate::printgrid::{print_directions, print_heatmap, printgrid, GridElement};
 use crate::read_file::read_file;
 use array2d::Array2D;
 use log::*;
 use std::env;
 use std::time::Instant;
 
+mod export;
 mod printgrid;
 mod read_file;
 
@@ -30,18 +32,22 @@ impl Beam {
     }
     fn walk(&mut self, grid: &mut Array2D<GridElement>, hist: &mut Vec<Beam>) {
         debug!("dir={:?} cur={:?}", self.dir, self.cur);
-        let (row, col) = (self.cur.0 as usize, self.cur.1 as usize);
-        let ce: &mut GridElement;
-        match grid.get_mut(row, col) {
-            None => {
-                return;
-            }
-            Some(fe) => {
-                ce = fe;
+        match grid.get_mut(self.cur.0, self.cur.1) {
+            None => {}
+            Some(ce) => {
+                ce.add_beam(self.dir);
+                self.pass(grid, hist);
             }
         }
-
-        ce.energized = true;
+    }
+    // Turns or splits the beam on its cell and moves on. The cell is already counted, so a
+    // split beam starts here and the splitter shows one beam with the incoming direction
+    fn pass(&mut self, grid: &mut Array2D<GridElement>, hist: &mut Vec<Beam>) {
+        let (row, col) = self.cur;
+        let ce = match grid.get(row, col) {
+            None => return,
+            Some(fe) => fe.clone(),
+        };
         match ce.typ {
             '/' => {
                 self.dir = match self.dir {
@@ -69,7 +75,7 @@ impl Beam {
                             dir: (-1, 0),
                         };
                         // debug!("| cur={:?} dir={:?} {:?} hit me ", self.cur, self.dir, &b);
-                        b.walk(grid, hist);
+                        b.pass(grid, hist);
                         (1, 0)
                     }
                     _ => self.dir,
@@ -83,7 +89,7 @@ impl Beam {
                             dir: (0, 1),
                         };
                         // debug!("- cur={:?} dir={:?} {:?} hit me ", self.cur, self.dir, &b);
-                        b.walk(grid, hist);
+                        b.pass(grid, hist);
                         (0, -1)
                     }
                     _ => self.dir,
@@ -92,14 +98,16 @@ impl Beam {
             _ => self.dir = self.dir,
         }
 
-        if let Some(nextgridele) = match self.dir {
+        match match self.dir {
             (-1, 0) if row >= 1 => grid.get(row - 1, col),
             (1, 0) => grid.get(row + 1, col),
             (0, -1) if col >= 1 => grid.get(row, col - 1),
             (0, 1) => grid.get(row, col + 1),
             _ => None,
         } {
-            self.cur = (nextgridele.row, nextgridele.col);
+            Some(nextgridele) => self.cur = (nextgridele.row, nextgridele.col),
+            // beam leaves the grid
+            None => return,
         }
 
         if Beam::is_in_hist(hist, self.cur.0, self.cur.1, self.dir) {
@@ -124,9 +132,7 @@ fn count_energized(grid: &Array2D<GridElement>) -> i32 {
 fn reset_energized(grid: &mut Array2D<GridElement>) {
     for (x, y) in grid.indices_row_major() {
         if let Some(element) = grid.get_mut(x, y) {
-            if element.energized {
-                element.energized = false;
-            }
+            element.reset();
         }
     }
 }
@@ -136,9 +142,18 @@ fn main() {
     let start = Instant::now();
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        println!("Usage: {} <filename>", args[0]);
+        println!(
+            "Usage: {} <filename> [--heatmap] [--arrows] [--image <file.ppm|file.png>]",
+            args[0]
+        );
         return;
     }
+    let heatmap = args.iter().any(|a| a == "--heatmap");
+    let arrows = args.iter().any(|a| a == "--arrows");
+    let image = args
+        .iter()
+        .position(|a| a == "--image")
+        .and_then(|i| args.get(i + 1));
     let inputtxt;
     match read_file(&args[1]) {
         Ok(contents) => {
@@ -173,6 +188,17 @@ fn main() {
     b.walk(&mut grid, &mut hist);
 
     printgrid(&grid);
+    if heatmap {
+        print_heatmap(&grid);
+    }
+    if arrows {
+        print_directions(&grid);
+    }
+    if let Some(file_name) = image {
+        if let Err(e) = write_image(&grid, file_name, 8) {
+            println!("Error writing image: {}", e);
+        }
+    }
     println!("part 1: {}", count_energized(&grid));
     println!("time elapsed: {:?}", start.elapsed());
 
@@ -7,6 +7,10 @@ pub struct GridElement {
     pub row: usize,
     pub typ: char,
     pub energized: bool,
+    /// number of beams that passed through this cell
+    pub beams: u32,
+    /// bitmask of beam directions, see `dir_bit`
+    pub dirs: u8,
 }
 impl GridElement {
     pub fn new(col: usize, row: usize, typ: char) -> GridElement {
@@ -15,8 +19,35 @@ impl GridElement {
             row,
             typ,
             energized: false,
+            beams: 0,
+            dirs: 0,
         }
     }
+    pub fn add_beam(&mut self, dir: (i8, i8)) {
+        self.energized = true;
+        self.beams += 1;
+        self.dirs |= dir_bit(dir);
+    }
+    pub fn reset(&mut self) {
+        self.energized = false;
+        self.beams = 0;
+        self.dirs = 0;
+    }
+}
+
+pub const UP: u8 = 1;
+pub const RIGHT: u8 = 2;
+pub const DOWN: u8 = 4;
+pub const LEFT: u8 = 8;
+
+pub fn dir_bit(dir: (i8, i8)) -> u8 {
+    match dir {
+        (-1, 0) => UP,
+        (0, 1) => RIGHT,
+        (1, 0) => DOWN,
+        (0, -1) => LEFT,
+        _ => 0,
+    }
 }
 pub fn printgrid(grid: &Array2D<GridElement>) {
     if !log_enabled!(Level::Debug) {
@@ -52,3 +83,101 @@ pub fn printgrid(grid: &Array2D<GridElement>) {
         println!();
     }
 }
+
+pub fn max_beams(grid: &Array2D<GridElement>) -> u32 {
+    grid.elements_row_major_iter()
+        .map(|ge| ge.beams)
+        .max()
+        .unwrap_or(0)
+}
+
+/// 256 colour palette from cold (blue) to hot (red)
+const HEAT: [u8; 12] = [17, 19, 21, 27, 33, 39, 45, 49, 118, 226, 208, 196];
+
+fn heat_index(beams: u32, max: u32) -> usize {
+    if beams == 0 || max == 0 {
+        return 0;
+    }
+    ((beams - 1) as usize * (HEAT.len() - 1)) / (max.max(2) - 1) as usize
+}
+
+/// Prints the grid with each energized cell coloured by its beam count
+pub fn print_heatmap(grid: &Array2D<GridElement>) {
+    let max = max_beams(grid);
+    for row in grid.rows_iter() {
+        for element in row {
+            if element.beams == 0 {
+                print!("{}", element.typ);
+            } else {
+                print!(
+                    "\x1b[48;5;{}m{}\x1b[0m",
+                    HEAT[heat_index(element.beams, max)],
+                    count_glyph(element.beams)
+                );
+            }
+        }
+        println!();
+    }
+    println!("max beams per cell: {}", max);
+}
+
+fn count_glyph(beams: u32) -> char {
+    match beams {
+        0 => '.',
+        1..=9 => char::from_digit(beams, 10).unwrap(),
+        _ => '+',
+    }
+}
+
+pub fn arrow_glyph(dirs: u8) -> char {
+    match dirs {
+        0 => '.',
+        UP => '↑',
+        RIGHT => '→',
+        DOWN => '↓',
+        LEFT => '←',
+        d if d == UP | DOWN => '↕',
+        d if d == LEFT | RIGHT => '↔',
+        _ => '┼',
+    }
+}
+
+/// Prints the beam directions on top of the mirrors, mirrors win over arrows
+pub fn print_directions(grid: &Array2D<GridElement>) {
+    for row in grid.rows_iter() {
+        for element in row {
+            if element.typ != '.' {
+                print!("{}", element.typ);
+            } else {
+                print!("{}", arrow_glyph(element.dirs));
+            }
+        }
+        println!();
+    }
+}
+
+/// black -> red -> yellow -> white
+pub fn heat_rgb(beams: u32, max: u32) -> [u8; 3] {
+    if beams == 0 || max == 0 {
+        return [0, 0, 0];
+    }
+    let t = beams as f32 / max as f32 * 3.0;
+    let r = t.min(1.0);
+    let g = (t - 1.0).clamp(0.0, 1.0);
+    let b = (t - 2.0).clamp(0.0, 1.0);
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+/// RGB pixels of the heatmap, every cell is a `scale` x `scale` square
+pub fn heatmap_pixels(grid: &Array2D<GridElement>, scale: usize) -> (usize, usize, Vec<u8>) {
+    let max = max_beams(grid);
+    let (hei, wid) = (grid.num_rows() * scale, grid.num_columns() * scale);
+    let mut data = Vec::with_capacity(hei * wid * 3);
+    for y in 0..hei {
+        for x in 0..wid {
+            let ge = grid.get(y / scale, x / scale).unwrap();
+            data.extend_from_slice(&heat_rgb(ge.beams, max));
+        }
+    }
+    (wid, hei, data)
+}
@@ -24,3 +24,45 @@ fn test_notin() {
     println!("{:?}", &hist);
     assert!(!Beam::is_in_hist(&hist, 3, 12, (-1, 0)));
 }
+
+#[test]
+fn test_arrow_glyph() {
+    use crate::printgrid::{arrow_glyph, dir_bit};
+    assert_eq!(arrow_glyph(dir_bit((0, 1))), '→');
+    assert_eq!(arrow_glyph(dir_bit((1, 0)) | dir_bit((-1, 0))), '↕');
+}
+
+#[test]
+fn test_beam_counts() {
+    use crate::printgrid::RIGHT;
+    let mut grid = Array2D::filled_with(GridElement::new(0, 0, '.'), 1, 3);
+    for col in 0..3 {
+        let ge = grid.get_mut(0, col).unwrap();
+        ge.col = col;
+    }
+    let mut b = Beam::new(0, 0, (0, 1));
+    b.walk(&mut grid, &mut Vec::new());
+    assert!(grid
+        .elements_row_major_iter()
+        .all(|ge| ge.beams == 1 && ge.dirs == RIGHT));
+}
+
+#[test]
+fn test_splitter_counted_once() {
+    use crate::printgrid::{DOWN, RIGHT, UP};
+    // .|.  the beam enters from the left and splits up and down
+    let mut grid = Array2D::filled_with(GridElement::new(0, 0, '.'), 3, 3);
+    for (row, col) in grid.indices_row_major() {
+        let ge = grid.get_mut(row, col).unwrap();
+        ge.row = row;
+        ge.col = col;
+    }
+    grid.get_mut(1, 1).unwrap().typ = '|';
+    let mut b = Beam::new(1, 0, (0, 1));
+    b.walk(&mut grid, &mut Vec::new());
+    let splitter = grid.get(1, 1).unwrap();
+    assert_eq!(splitter.beams, 1);
+    assert_eq!(splitter.dirs, RIGHT);
+    assert_eq!(grid.get(0, 1).unwrap().dirs, UP);
+    assert_eq!(grid.get(2, 1).unwrap().dirs, DOWN);
+}
@@ -1,4 +1,6 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::collections::VecDeque;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::path::Path;
@@ -6,18 +8,134 @@ use std::process::exit;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-#[command(about = "Reads numbers from a text file and stores them in a vector")]
+#[command(about = "Counts depth increases over sliding windows of a sonar sweep")]
 struct Args {
     /// Path to the input file
     #[arg(default_value = "input_example.txt")]
     filename: String,
+
+    /// Sliding window sizes to compare, e.g. `-w 1,3`
+    #[arg(short, long, value_delimiter = ',', default_values_t = [1, 3])]
+    windows: Vec<usize>,
+
+    /// Aggregate functions applied to each window
+    #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = [Aggregate::Sum])]
+    aggregates: Vec<Aggregate>,
 }
 
-fn read_numbers_from_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<i32>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Aggregate {
+    Sum,
+    /// The sum divided by the window size, so it counts exactly like `sum`
+    Mean,
+    Min,
+    Max,
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Aggregate::Sum => "sum",
+            Aggregate::Mean => "mean",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Keeps the last `size` values and counts how the aggregate changes between windows
+struct WindowTracker {
+    size: usize,
+    aggregate: Aggregate,
+    window: VecDeque<i32>,
+    sum: i64,
+    // candidates for the minimum and maximum, increasing and decreasing, so every push is
+    // amortised O(1) and the front is the aggregate
+    mins: VecDeque<i32>,
+    maxs: VecDeque<i32>,
+    previous: Option<f64>,
+    increases: usize,
+    decreases: usize,
+    flat: usize,
+}
+
+impl WindowTracker {
+    fn new(size: usize, aggregate: Aggregate) -> WindowTracker {
+        WindowTracker {
+            size,
+            aggregate,
+            window: VecDeque::with_capacity(size),
+            sum: 0,
+            mins: VecDeque::new(),
+            maxs: VecDeque::new(),
+            previous: None,
+            increases: 0,
+            decreases: 0,
+            flat: 0,
+        }
+    }
+
+    fn value(&self) -> f64 {
+        match self.aggregate {
+            Aggregate::Sum => self.sum as f64,
+            Aggregate::Mean => self.sum as f64 / self.size as f64,
+            Aggregate::Min => self.mins[0] as f64,
+            Aggregate::Max => self.maxs[0] as f64,
+        }
+    }
+
+    fn push(&mut self, number: i32) {
+        self.window.push_back(number);
+        self.sum += number as i64;
+        while self.mins.back().is_some_and(|&m| m > number) {
+            self.mins.pop_back();
+        }
+        self.mins.push_back(number);
+        while self.maxs.back().is_some_and(|&m| m < number) {
+            self.maxs.pop_back();
+        }
+        self.maxs.push_back(number);
+        if self.window.len() > self.size {
+            let dropped = self.window.pop_front().unwrap();
+            self.sum -= dropped as i64;
+            // equal values stay in the deques, so only one copy leaves with the window
+            if self.mins[0] == dropped {
+                self.mins.pop_front();
+            }
+            if self.maxs[0] == dropped {
+                self.maxs.pop_front();
+            }
+        }
+        if self.window.len() < self.size {
+            return;
+        }
+        let value = self.value();
+        if let Some(previous) = self.previous {
+            if value > previous {
+                self.increases += 1;
+            } else if value < previous {
+                self.decreases += 1;
+            } else {
+                self.flat += 1;
+            }
+        }
+        self.previous = Some(value);
+    }
+}
+
+impl Display for WindowTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "window {} {}: increases: {}, decreases: {}, flat: {}",
+            self.size, self.aggregate, self.increases, self.decreases, self.flat
+        )
+    }
+}
 
-    let numbers: Vec<i32> = reader
+fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item = i32> {
+    reader
         .lines()
         .map_while(Result::ok)
         .map(|line| line.trim().to_string())
@@ -27,27 +145,44 @@ fn read_numbers_from_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<i32>> {
                 .map_err(|e| eprintln!("Warning: Could not parse '{line}': {e}"))
                 .ok()
         })
-        .collect();
+}
 
-    Ok(numbers)
+fn open_numbers<P: AsRef<Path>>(path: P) -> io::Result<impl Iterator<Item = i32>> {
+    let file = File::open(path)?;
+    Ok(read_numbers(BufReader::new(file)))
 }
 
 fn main() {
     let args = Args::parse();
-    let numbers = match read_numbers_from_file(&args.filename) {
-        Ok(numbers) => {
-            println!("Read {} numbers from file:", numbers.len());
-            println!("number of numbers: {:?}", numbers.len());
-            numbers
-        }
+    if args.windows.contains(&0) {
+        eprintln!("Window sizes must be at least 1");
+        exit(1);
+    }
+    let numbers = match open_numbers(&args.filename) {
+        Ok(numbers) => numbers,
         Err(e) => {
             eprintln!("Could not read {e} {}", args.filename);
             exit(2);
         }
     };
-    let increases = numbers.windows(2).filter(|w| w[1] > w[0]).count();
-    println!("increases: {increases:?}");
-    let sums: Vec<i32> = numbers.windows(3).map(|w| w.iter().sum()).collect();
-    let sum_increases = sums.windows(2).filter(|w| w[1] > w[0]).count();
-    println!("sum of increases in 3 windows: {sum_increases:?}");
+    let mut trackers: Vec<WindowTracker> = args
+        .windows
+        .iter()
+        .flat_map(|&size| {
+            args.aggregates
+                .iter()
+                .map(move |&aggregate| WindowTracker::new(size, aggregate))
+        })
+        .collect();
+    let mut count = 0;
+    for number in numbers {
+        count += 1;
+        for tracker in trackers.iter_mut() {
+            tracker.push(number);
+        }
+    }
+    println!("number of numbers: {count}");
+    for tracker in &trackers {
+        println!("{tracker}");
+    }
 }
@@ -1,11 +1,14 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use program::{ParseError, Program};
 use std::fmt::Display;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufWriter, Write};
 use std::path::Path;
 use std::process::exit;
 use std::str::FromStr;
 
+mod program;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(about = "Reads numbers from a text file and stores them in a vector")]
@@ -13,8 +16,31 @@ struct Args {
     /// Path to the input file
     #[arg(default_value = "input_example.txt")]
     filename: String,
+
+    /// Write the aim trajectory to this file
+    #[arg(short, long)]
+    export: Option<String>,
+
+    /// Export the trajectory without aim instead
+    #[arg(long)]
+    plain: bool,
+
+    /// Format of the exported trajectory
+    #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Report the first step where the depth exceeds this value
+    #[arg(short, long)]
+    threshold: Option<i32>,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, Default)]
 struct Coordinates {
     horizontal: i32,
     depth: i32,
@@ -41,6 +67,10 @@ impl Coordinates {
                 horizontal: self.horizontal + value,
                 ..self
             },
+            Instruction::Back(value) => Coordinates {
+                horizontal: self.horizontal - value,
+                ..self
+            },
             Instruction::Up(value) => Coordinates {
                 depth: self.depth - value,
                 ..self
@@ -59,6 +89,11 @@ impl Coordinates {
                 depth: self.depth + value * self.aim,
                 ..self
             },
+            Instruction::Back(value) => Coordinates {
+                horizontal: self.horizontal - value,
+                depth: self.depth - value * self.aim,
+                ..self
+            },
             Instruction::Up(value) => Coordinates {
                 aim: self.aim - value,
                 ..self
@@ -71,32 +106,139 @@ impl Coordinates {
     }
 }
 
+type Reducer = fn(Coordinates, &Instruction) -> Coordinates;
+
+/// Yields the coordinates after every instruction
+fn trajectory(
+    instructions: &[Instruction],
+    reducer: Reducer,
+) -> impl Iterator<Item = Coordinates> + '_ {
+    instructions
+        .iter()
+        .scan(Coordinates::default(), move |state, instruction| {
+            *state = reducer(*state, instruction);
+            Some(*state)
+        })
+}
+
 fn calculate_coordinates(instructions: &[Instruction]) -> Coordinates {
-    instructions.iter().fold(
-        Coordinates {
-            horizontal: 0,
-            depth: 0,
-            aim: 0,
-        },
-        Coordinates::reducer,
-    )
+    trajectory(instructions, Coordinates::reducer)
+        .last()
+        .unwrap_or_default()
 }
 
 fn aim_calculate_coordinates(instructions: &[Instruction]) -> Coordinates {
-    instructions.iter().fold(
-        Coordinates {
-            horizontal: 0,
-            depth: 0,
-            aim: 0,
-        },
-        Coordinates::aim_reducer,
-    )
+    trajectory(instructions, Coordinates::aim_reducer)
+        .last()
+        .unwrap_or_default()
+}
+
+struct Summary {
+    max_depth: i32,
+    max_depth_step: usize,
+    threshold_step: Option<usize>,
 }
 
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "max depth: {} at step {}",
+            self.max_depth, self.max_depth_step
+        )?;
+        if let Some(step) = self.threshold_step {
+            write!(f, ", threshold first exceeded at step {step}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps are counted from 1, step 0 is the starting point
+fn summarize(trajectory: impl Iterator<Item = Coordinates>, threshold: Option<i32>) -> Summary {
+    let mut summary = Summary {
+        max_depth: 0,
+        max_depth_step: 0,
+        threshold_step: None,
+    };
+    for (step, coordinates) in trajectory.enumerate().map(|(i, c)| (i + 1, c)) {
+        if coordinates.depth > summary.max_depth {
+            summary.max_depth = coordinates.depth;
+            summary.max_depth_step = step;
+        }
+        if summary.threshold_step.is_none() && threshold.is_some_and(|t| coordinates.depth > t) {
+            summary.threshold_step = Some(step);
+        }
+    }
+    summary
+}
+
+fn write_trajectory<W: Write>(
+    mut out: W,
+    instructions: &[Instruction],
+    reducer: Reducer,
+    format: Format,
+) -> io::Result<()> {
+    let steps = instructions
+        .iter()
+        .zip(trajectory(instructions, reducer))
+        .enumerate()
+        .map(|(i, (instruction, c))| (i + 1, instruction, c));
+    match format {
+        Format::Csv => {
+            writeln!(out, "step,instruction,horizontal,depth,aim")?;
+            for (step, instruction, c) in steps {
+                writeln!(
+                    out,
+                    "{step},{instruction},{},{},{}",
+                    c.horizontal, c.depth, c.aim
+                )?;
+            }
+        }
+        Format::Json => {
+            writeln!(out, "[")?;
+            for (step, instruction, c) in steps {
+                let separator = if step < instructions.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "  {{\"step\": {step}, \"instruction\": \"{instruction}\", \"horizontal\": {}, \"depth\": {}, \"aim\": {}}}{separator}",
+                    c.horizontal, c.depth, c.aim
+                )?;
+            }
+            writeln!(out, "]")?;
+        }
+    }
+    out.flush()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Instruction {
     Forward(i32),
     Up(i32),
     Down(i32),
+    Back(i32),
+}
+
+impl Instruction {
+    fn from_parts(name: &str, value: i32) -> Result<Instruction, ParseInstructionError> {
+        match name {
+            "forward" => Ok(Instruction::Forward(value)),
+            "up" => Ok(Instruction::Up(value)),
+            "down" => Ok(Instruction::Down(value)),
+            "back" => Ok(Instruction::Back(value)),
+            _ => Err(ParseInstructionError),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Forward(value) => write!(f, "forward {value}"),
+            Instruction::Up(value) => write!(f, "up {value}"),
+            Instruction::Down(value) => write!(f, "down {value}"),
+            Instruction::Back(value) => write!(f, "back {value}"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -113,41 +255,30 @@ impl FromStr for Instruction {
 
         let value = parts[1].parse::<i32>().map_err(|_| ParseInstructionError)?;
 
-        match parts[0] {
-            "forward" => Ok(Instruction::Forward(value)),
-            "up" => Ok(Instruction::Up(value)),
-            "down" => Ok(Instruction::Down(value)),
-            _ => Err(ParseInstructionError),
-        }
+        Instruction::from_parts(parts[0], value)
     }
 }
 
-fn read_instructions_from_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Instruction>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-
-    let instructions: Vec<Instruction> = reader
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_string())
-        .filter(|line| !line.is_empty())
-        .filter_map(|line| {
-            line.parse::<Instruction>()
-                .map_err(|_| eprintln!("Warning: Could not parse instruction '{line}'"))
-                .ok()
-        })
-        .collect();
-
-    Ok(instructions)
+fn read_instructions_from_file<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<Result<Vec<Instruction>, Vec<ParseError>>> {
+    let source = std::fs::read_to_string(path)?;
+    Ok(Program::parse(&source).and_then(|program| program.expand()))
 }
 
 fn main() {
     let args = Args::parse();
     let instructions = match read_instructions_from_file(&args.filename) {
-        Ok(instructions) => {
+        Ok(Ok(instructions)) => {
             println!("Read {} instructions from file", instructions.len());
             instructions
         }
+        Ok(Err(errors)) => {
+            for error in errors {
+                eprintln!("Error: {error}");
+            }
+            exit(1);
+        }
         Err(e) => {
             eprintln!("Could not read {e} {}", args.filename);
             exit(2);
@@ -161,4 +292,34 @@ fn main() {
         "Aim Coordinates: {}",
         aim_calculate_coordinates(&instructions)
     );
+    println!(
+        "Summary: {}",
+        summarize(
+            trajectory(&instructions, Coordinates::reducer),
+            args.threshold
+        )
+    );
+    println!(
+        "Aim Summary: {}",
+        summarize(
+            trajectory(&instructions, Coordinates::aim_reducer),
+            args.threshold
+        )
+    );
+
+    if let Some(export) = &args.export {
+        let reducer: Reducer = if args.plain {
+            Coordinates::reducer
+        } else {
+            Coordinates::aim_reducer
+        };
+        let result = File::create(export).and_then(|file| {
+            write_trajectory(BufWriter::new(file), &instructions, reducer, args.format)
+        });
+        if let Err(e) = result {
+            eprintln!("Could not write {e} {export}");
+            exit(2);
+        }
+        println!("Trajectory written to {export}");
+    }
 }
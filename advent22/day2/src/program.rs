@@ -0,0 +1,447 @@
+//! Instruction language on top of the plain `forward`/`up`/`down` commands:
+//!
+//! ```text
+//! # comments run to the end of the line
+//! macro dive {
+//!     down 5
+//!     forward 2
+//! }
+//! repeat 3 {
+//!     dive
+//!     back 1
+//! }
+//! ```
+use crate::Instruction;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+const KEYWORDS: [&str; 6] = ["forward", "up", "down", "back", "repeat", "macro"];
+
+/// Programs that expand to more instructions are rejected instead of exhausting memory
+pub const MAX_INSTRUCTIONS: u64 = 10_000_000;
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub enum Statement {
+    Instruction(Instruction),
+    Repeat {
+        count: u32,
+        body: Vec<Statement>,
+        line: usize,
+    },
+    Call {
+        name: String,
+        line: usize,
+    },
+}
+
+#[derive(Default)]
+pub struct Program {
+    pub macros: HashMap<String, Vec<Statement>>,
+    pub body: Vec<Statement>,
+}
+
+struct Token<'a> {
+    line: usize,
+    text: &'a str,
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        for word in code.split_whitespace() {
+            let mut rest = word;
+            while let Some(i) = rest.find(['{', '}']) {
+                if i > 0 {
+                    tokens.push(Token {
+                        line: n + 1,
+                        text: &rest[..i],
+                    });
+                }
+                tokens.push(Token {
+                    line: n + 1,
+                    text: &rest[i..i + 1],
+                });
+                rest = &rest[i + 1..];
+            }
+            if !rest.is_empty() {
+                tokens.push(Token {
+                    line: n + 1,
+                    text: rest,
+                });
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    program: Program,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Next token, which has to be on `line`
+    fn next_on_line(&mut self, line: usize, what: &str) -> Result<&'a str, ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) if token.line == line => {
+                self.pos += 1;
+                Ok(token.text)
+            }
+            _ => Err(ParseError::new(line, format!("expected {what}"))),
+        }
+    }
+
+    fn skip_line(&mut self, line: usize) {
+        while self.peek().is_some_and(|t| t.line == line) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_block(&mut self, closed_at: Option<usize>) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        loop {
+            let Some(token) = self.peek() else {
+                if let Some(line) = closed_at {
+                    self.errors
+                        .push(ParseError::new(line, "block is never closed with '}'"));
+                }
+                return statements;
+            };
+            let line = token.line;
+            if token.text == "}" {
+                self.pos += 1;
+                if closed_at.is_some() {
+                    return statements;
+                }
+                self.errors.push(ParseError::new(line, "unexpected '}'"));
+                continue;
+            }
+            match self.parse_statement() {
+                Ok(Some(statement)) => statements.push(statement),
+                Ok(None) => {}
+                Err(e) => {
+                    self.skip_line(e.line);
+                    self.errors.push(e);
+                }
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Option<Statement>, ParseError> {
+        let Token { line, text } = self.tokens[self.pos];
+        self.pos += 1;
+        match text {
+            "repeat" => {
+                let count = self.next_on_line(line, "repeat count")?;
+                let count = count.parse::<u32>().map_err(|_| {
+                    ParseError::new(line, format!("invalid repeat count '{count}'"))
+                })?;
+                self.open_block(line)?;
+                let body = self.parse_block(Some(line));
+                Ok(Some(Statement::Repeat { count, body, line }))
+            }
+            "macro" => {
+                let name = self.next_on_line(line, "macro name")?;
+                if !is_identifier(name) || KEYWORDS.contains(&name) {
+                    return Err(ParseError::new(
+                        line,
+                        format!("invalid macro name '{name}'"),
+                    ));
+                }
+                self.open_block(line)?;
+                let body = self.parse_block(Some(line));
+                if self.program.macros.insert(name.to_string(), body).is_some() {
+                    return Err(ParseError::new(
+                        line,
+                        format!("macro '{name}' defined twice"),
+                    ));
+                }
+                Ok(None)
+            }
+            "forward" | "up" | "down" | "back" => {
+                let value = self.next_on_line(line, "a value")?;
+                let value = value
+                    .parse::<i32>()
+                    .map_err(|_| ParseError::new(line, format!("invalid value '{value}'")))?;
+                let instruction = Instruction::from_parts(text, value)
+                    .map_err(|_| ParseError::new(line, format!("unknown instruction '{text}'")))?;
+                Ok(Some(Statement::Instruction(instruction)))
+            }
+            name if self
+                .peek()
+                .is_some_and(|t| t.line == line && t.text.parse::<i32>().is_ok()) =>
+            {
+                Err(ParseError::new(
+                    line,
+                    format!("unknown instruction '{name}'"),
+                ))
+            }
+            name if is_identifier(name) => Ok(Some(Statement::Call {
+                name: name.to_string(),
+                line,
+            })),
+            other => Err(ParseError::new(line, format!("unexpected '{other}'"))),
+        }
+    }
+
+    fn open_block(&mut self, line: usize) -> Result<(), ParseError> {
+        match self.next_on_line(line, "'{'")? {
+            "{" => Ok(()),
+            other => Err(ParseError::new(
+                line,
+                format!("expected '{{', found '{other}'"),
+            )),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Program {
+    /// Parses the whole source and collects every error instead of stopping at the first one
+    pub fn parse(source: &str) -> Result<Program, Vec<ParseError>> {
+        let mut parser = Parser {
+            tokens: tokenize(source),
+            pos: 0,
+            program: Program::default(),
+            errors: Vec::new(),
+        };
+        parser.program.body = parser.parse_block(None);
+        let program = &parser.program;
+        program.check_calls(&program.body, &mut parser.errors);
+        for body in program.macros.values() {
+            program.check_calls(body, &mut parser.errors);
+        }
+        if parser.errors.is_empty() {
+            Ok(parser.program)
+        } else {
+            parser.errors.sort_by_key(|e| e.line);
+            Err(parser.errors)
+        }
+    }
+
+    fn check_calls(&self, statements: &[Statement], errors: &mut Vec<ParseError>) {
+        for statement in statements {
+            match statement {
+                Statement::Instruction(_) => {}
+                Statement::Repeat { body, .. } => self.check_calls(body, errors),
+                Statement::Call { name, line } => {
+                    if !self.macros.contains_key(name) {
+                        errors.push(ParseError::new(*line, format!("unknown macro '{name}'")));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flattens repeats and macro calls into the plain instruction list the reducers run on.
+    /// A repeat or call that would grow the list past `MAX_INSTRUCTIONS` is reported on its line
+    pub fn expand(&self) -> Result<Vec<Instruction>, Vec<ParseError>> {
+        let mut instructions = Vec::new();
+        let mut stack = Vec::new();
+        let mut sizes = HashMap::new();
+        self.expand_into(&self.body, &mut instructions, &mut stack, &mut sizes)
+            .map_err(|e| vec![e])?;
+        Ok(instructions)
+    }
+
+    /// Number of instructions the statements expand to, saturating at `u64::MAX`.
+    /// `sizes` caches the macros so nested calls are only counted once
+    fn size<'a>(
+        &'a self,
+        statements: &'a [Statement],
+        stack: &mut Vec<&'a str>,
+        sizes: &mut HashMap<&'a str, u64>,
+    ) -> Result<u64, ParseError> {
+        let mut total: u64 = 0;
+        for statement in statements {
+            let size = match statement {
+                Statement::Instruction(_) => 1,
+                Statement::Repeat { count, body, .. } => {
+                    self.size(body, stack, sizes)?.saturating_mul(*count as u64)
+                }
+                Statement::Call { name, line } => match sizes.get(name.as_str()) {
+                    Some(size) => *size,
+                    None => {
+                        if stack.contains(&name.as_str()) {
+                            return Err(ParseError::new(
+                                *line,
+                                format!("macro '{name}' calls itself"),
+                            ));
+                        }
+                        stack.push(name);
+                        let size = self.size(&self.macros[name], stack, sizes)?;
+                        stack.pop();
+                        sizes.insert(name, size);
+                        size
+                    }
+                },
+            };
+            total = total.saturating_add(size);
+        }
+        Ok(total)
+    }
+
+    fn expand_into<'a>(
+        &'a self,
+        statements: &'a [Statement],
+        out: &mut Vec<Instruction>,
+        stack: &mut Vec<&'a str>,
+        sizes: &mut HashMap<&'a str, u64>,
+    ) -> Result<(), ParseError> {
+        for statement in statements {
+            // sized before anything is expanded, this also finds macros that call themselves
+            if let Statement::Repeat { line, .. } | Statement::Call { line, .. } = statement {
+                let size = self
+                    .size(std::slice::from_ref(statement), stack, sizes)?
+                    .saturating_add(out.len() as u64);
+                if size > MAX_INSTRUCTIONS {
+                    return Err(ParseError::new(
+                        *line,
+                        format!("program expands to more than {MAX_INSTRUCTIONS} instructions"),
+                    ));
+                }
+            }
+            match statement {
+                Statement::Instruction(instruction) => out.push(*instruction),
+                Statement::Repeat { count, body, .. } => {
+                    for _ in 0..*count {
+                        self.expand_into(body, out, stack, sizes)?;
+                    }
+                }
+                Statement::Call { name, .. } => {
+                    stack.push(name);
+                    self.expand_into(&self.macros[name], out, stack, sizes)?;
+                    stack.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+        Program::parse(source)?.expand()
+    }
+
+    fn lines(errors: Vec<ParseError>) -> Vec<usize> {
+        errors.iter().map(|e| e.line).collect()
+    }
+
+    #[test]
+    fn test_expand_repeat_and_macro() {
+        let source = "\
+# dive twice
+macro dive {
+    down 5
+    forward 2
+}
+repeat 2 { dive }
+back 1
+";
+        assert_eq!(
+            expand(source),
+            Ok(vec![
+                Instruction::Down(5),
+                Instruction::Forward(2),
+                Instruction::Down(5),
+                Instruction::Forward(2),
+                Instruction::Back(1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_are_per_line() {
+        let source = "\
+forward x
+sideways 3
+up 1
+repeat many {
+}
+call_me
+";
+        // the '}' of the rejected repeat is left over
+        let errors = Program::parse(source).err().unwrap();
+        assert_eq!(lines(errors), vec![1, 2, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_unclosed_block() {
+        let errors = Program::parse("repeat 2 {\nup 1\n").err().unwrap();
+        assert_eq!(
+            errors,
+            vec![ParseError::new(1, "block is never closed with '}'")]
+        );
+    }
+
+    #[test]
+    fn test_macro_calls_itself() {
+        let source = "\
+macro a { b }
+macro b {
+    up 1
+    a
+}
+a
+";
+        let errors = expand(source).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("calls itself"));
+    }
+
+    #[test]
+    fn test_repeat_past_limit() {
+        let errors = expand("up 1\nrepeat 4000000000 { up 1 down 1 }\n")
+            .err()
+            .unwrap();
+        assert_eq!(lines(errors), vec![2]);
+    }
+
+    #[test]
+    fn test_macro_doubling_past_limit() {
+        // every macro calls the one before twice, m39 expands to 2^39 instructions
+        let mut source = String::from("macro m0 { up 1 }\n");
+        for i in 1..40 {
+            source += &format!("macro m{i} {{ m{} m{} }}\n", i - 1, i - 1);
+        }
+        source += "m39\n";
+        let errors = expand(&source).err().unwrap();
+        assert_eq!(lines(errors), vec![41]);
+        assert!(expand("macro m0 { up 1 }\nmacro m1 { m0 m0 }\nm1\n").is_ok_and(|i| i.len() == 2));
+    }
+}
@@ -2,7 +2,6 @@ use clap::Parser;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
-use std::num::ParseIntError;
 use std::path::Path;
 use std::process::exit;
 
@@ -15,29 +14,104 @@ struct Args {
     filename: String,
 }
 
-#[derive(Clone)]
+/// Diagnostic word of any width, bit 0 is the last character of the line
+#[derive(Clone, PartialEq)]
 struct Binary {
-    number: u64,
+    words: Vec<u64>,
+    width: usize,
 }
 
 impl Binary {
-    fn get_nth_bit(&self, n: u8) -> u8 {
-        (self.number >> n & 1) as u8
+    fn zeros(width: usize) -> Binary {
+        Binary {
+            words: vec![0; width.div_ceil(64)],
+            width,
+        }
+    }
+
+    fn get_nth_bit(&self, n: usize) -> u8 {
+        (self.words[n / 64] >> (n % 64) & 1) as u8
+    }
+
+    fn set_nth_bit(&mut self, n: usize) {
+        self.words[n / 64] |= 1 << (n % 64);
+    }
+
+    /// Positions of all set bits, lowest first
+    fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+
+    /// Bitwise invert within `width`
+    fn invert(&self) -> Binary {
+        let mut inverted = Binary {
+            words: self.words.iter().map(|word| !word).collect(),
+            width: self.width,
+        };
+        if let Some(last) = inverted.words.last_mut()
+            && !self.width.is_multiple_of(64)
+        {
+            *last &= (1 << (self.width % 64)) - 1;
+        }
+        inverted
+    }
+
+    /// Value as a number if it fits into 64 bits
+    fn to_u64(&self) -> Option<u64> {
+        match self.words.split_first() {
+            None => Some(0),
+            Some((first, rest)) => rest.iter().all(|&word| word == 0).then_some(*first),
+        }
+    }
+}
+
+impl std::fmt::Binary for Binary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for n in (0..self.width).rev() {
+            write!(f, "{}", self.get_nth_bit(n))?;
+        }
+        Ok(())
     }
 }
 
 impl Debug for Binary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.number)
+        write!(f, "{self:b}")
+    }
+}
+
+#[derive(Debug)]
+struct ParseBinaryError(char);
+
+impl std::fmt::Display for ParseBinaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid binary digit '{}'", self.0)
     }
 }
 
 impl std::str::FromStr for Binary {
-    type Err = ParseIntError;
+    type Err = ParseBinaryError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let number = u64::from_str_radix(s, 2)?;
-        Ok(Binary { number })
+        let mut binary = Binary::zeros(s.len());
+        for (n, c) in s.bytes().rev().enumerate() {
+            match c {
+                b'0' => {}
+                b'1' => binary.set_nth_bit(n),
+                _ => return Err(ParseBinaryError(c as char)),
+            }
+        }
+        Ok(binary)
     }
 }
 
@@ -45,27 +119,54 @@ impl std::str::FromStr for Binary {
 struct BinaryVec(Vec<Binary>);
 
 impl BinaryVec {
-    fn from_file<P: AsRef<Path>>(path: P, number_of_bits: &mut u8) -> io::Result<Self> {
+    /// Reads one word per line, all lines must have the same width
+    fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         let file = File::open(&path)?;
         let reader = BufReader::new(file);
 
-        let binaries: Vec<Binary> = reader
-            .lines()
-            .map_while(Result::ok)
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .map(|line| line.parse::<Binary>().unwrap())
-            .collect();
-
-        // Get length of first line
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let first_line = reader.lines().next().unwrap()?;
-        *number_of_bits = first_line.len() as u8;
+        let mut binaries: Vec<Binary> = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {message}", n + 1),
+                )
+            };
+            let binary = line.parse::<Binary>().map_err(|e| invalid(e.to_string()))?;
+            if let Some(first) = binaries.first()
+                && first.width != binary.width
+            {
+                return Err(invalid(format!(
+                    "width {} differs from width {} of the first line",
+                    binary.width, first.width
+                )));
+            }
+            binaries.push(binary);
+        }
 
         Ok(BinaryVec(binaries))
     }
 
+    fn width(&self) -> usize {
+        self.0.first().map_or(0, |binary| binary.width)
+    }
+
+    /// Number of ones in every column, counted in a single pass over all words
+    fn column_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.width()];
+        for binary in &self.0 {
+            for n in binary.ones() {
+                counts[n] += 1;
+            }
+        }
+        counts
+    }
+
     fn len(&self) -> usize {
         self.0.len()
     }
@@ -77,24 +178,96 @@ impl BinaryVec {
         self.0.retain(f);
     }
 
-    fn get_most_common_bit(&self, bit_position: u8) -> usize {
+    /// Number of ones at `bit_position`, counted from the least significant bit
+    fn count_bits_at_position(&self, bit_position: usize) -> usize {
         self.0
             .iter()
             .map(|number| number.get_nth_bit(bit_position) as usize)
             .sum()
-            / self.len()
     }
 
-    fn calculate_gamma(&self, number_of_bits: u8) -> u64 {
-        let threshold = self.len() / 2;
-        let mut result: u64 = 0;
-        for n in 0..number_of_bits {
-            if self.count_bits_at_position(n) > threshold {
-                result |= 1 << n;
+    /// Bit picked by `criterion` from the counts at `bit_position`
+    fn select_bit(&self, bit_position: usize, criterion: BitCriterion) -> u8 {
+        criterion.pick(self.count_bits_at_position(bit_position), self.len())
+    }
+
+    fn calculate_gamma(&self) -> Binary {
+        let mut result = Binary::zeros(self.width());
+        for (n, ones) in self.column_counts().into_iter().enumerate() {
+            if BitCriterion::OXYGEN.pick(ones, self.len()) == 1 {
+                result.set_nth_bit(n);
             }
         }
         result
     }
+
+    /// Keeps only the numbers matching the selected bit, starting at the most significant bit,
+    /// until a single number is left
+    fn life_support_rating(&self, criterion: BitCriterion) -> Option<Rating> {
+        let mut remaining = self.clone();
+        let mut trace = Vec::new();
+        for n in (0..self.width()).rev() {
+            if remaining.len() <= 1 {
+                break;
+            }
+            let bit = remaining.select_bit(n, criterion);
+            remaining.retain(|binary| binary.get_nth_bit(n) == bit);
+            trace.push(FilterStep {
+                bit_position: n,
+                bit,
+                remaining: remaining.len(),
+            });
+        }
+        remaining.first().map(|binary| Rating {
+            value: binary.clone(),
+            trace,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BitCriterion {
+    MostCommon { tie: u8 },
+    LeastCommon { tie: u8 },
+}
+
+impl BitCriterion {
+    const OXYGEN: BitCriterion = BitCriterion::MostCommon { tie: 1 };
+    const CO2: BitCriterion = BitCriterion::LeastCommon { tie: 0 };
+
+    /// Bit to keep given the number of ones out of `len` numbers. When every number has
+    /// the same bit, that bit is picked for both criteria so filtering never drops all numbers.
+    fn pick(self, ones: usize, len: usize) -> u8 {
+        let zeros = len - ones;
+        if ones == 0 || zeros == 0 {
+            return (ones > 0) as u8;
+        }
+        match self {
+            BitCriterion::MostCommon { tie } => match ones.cmp(&zeros) {
+                std::cmp::Ordering::Greater => 1,
+                std::cmp::Ordering::Less => 0,
+                std::cmp::Ordering::Equal => tie,
+            },
+            BitCriterion::LeastCommon { tie } => match ones.cmp(&zeros) {
+                std::cmp::Ordering::Greater => 0,
+                std::cmp::Ordering::Less => 1,
+                std::cmp::Ordering::Equal => tie,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FilterStep {
+    bit_position: usize,
+    bit: u8,
+    remaining: usize,
+}
+
+#[derive(Debug)]
+struct Rating {
+    value: Binary,
+    trace: Vec<FilterStep>,
 }
 
 impl std::ops::Deref for BinaryVec {
@@ -118,7 +291,7 @@ impl std::fmt::Binary for BinaryVec {
             if i > 0 {
                 write!(f, ", ")?;
             }
-            write!(f, "{:b}", binary.number)?;
+            write!(f, "{binary:b}")?;
         }
         write!(f, "]")
     }
@@ -133,11 +306,15 @@ impl<'a> IntoIterator for &'a BinaryVec {
     }
 }
 
+/// Decimal product if both factors fit into 64 bits
+fn product(a: &Binary, b: &Binary) -> Option<u128> {
+    Some(a.to_u64()? as u128 * b.to_u64()? as u128)
+}
+
 fn main() {
     let args = Args::parse();
 
-    let number_of_bits = &mut 0;
-    let numbers = match BinaryVec::from_file(&args.filename, number_of_bits) {
+    let numbers = match BinaryVec::from_file(&args.filename) {
         Ok(binary_vec) => {
             println!("Read {} instructions from file", binary_vec.len());
             binary_vec
@@ -150,42 +327,41 @@ fn main() {
 
     // Process instructions here
     println!("{:?} numbers parsed", numbers.len());
-    println!("{number_of_bits} bits per number");
+    println!("{} bits per number", numbers.width());
     println!("{numbers:b}");
 
-    let gamma_rate = numbers.calculate_gamma(*number_of_bits);
-
+    let gamma_rate = numbers.calculate_gamma();
     // Bitwise invert, but only the first number of bits
-    let mask = (1u64 << *number_of_bits) - 1;
-    println!("mask {mask:b}");
-    let epsilon_rate = (!gamma_rate) & mask;
-
-    println!("{gamma_rate}");
-    println!("{epsilon_rate}");
+    let epsilon_rate = gamma_rate.invert();
 
     // print in binary
     println!("gamma rate = {gamma_rate:b}");
     println!("epsilon rate = {epsilon_rate:b}");
-    println!("result of the first {}", epsilon_rate * gamma_rate);
-
-    // let n = *number_of_bits;
-    // let number_of_first_bits: Vec<i32> = numbers.iter().map(|x| (x >> (n - 1)) & 1).collect();
-    // println!("{number_of_first_bits:?}");
-
-    let mut filtered_numbers = numbers.clone();
-
-    for n in (0..*number_of_bits).rev() {
-        println!("  before filtering for bit {n}: {filtered_numbers:b}");
-        println!(
-            "bit position {}, remaining numbers: {}",
-            n,
-            filtered_numbers.len()
-        );
-        let new_gamma = filtered_numbers.calculate_gamma(n);
-        println!("new gamma = {new_gamma:b}");
-        filtered_numbers
-            .retain(|binary| ((binary.number >> n) & 1) == ((new_gamma as u64 >> n) & 1));
-        println!("  after  filtering for bit {n}: {filtered_numbers:b}");
-    }
-    println!("final filtered numbers {filtered_numbers:b}");
+    match product(&gamma_rate, &epsilon_rate) {
+        Some(result) => println!("result of the first {result}"),
+        None => println!("result of the first is too wide for a decimal number"),
+    }
+
+    let mut ratings = Vec::new();
+    for (name, criterion) in [
+        ("oxygen generator", BitCriterion::OXYGEN),
+        ("CO2 scrubber", BitCriterion::CO2),
+    ] {
+        let Some(rating) = numbers.life_support_rating(criterion) else {
+            eprintln!("No numbers left for the {name} rating");
+            exit(1);
+        };
+        for step in &rating.trace {
+            println!(
+                "  {name}: bit {} keep {} -> {} remaining",
+                step.bit_position, step.bit, step.remaining
+            );
+        }
+        println!("{name} rating = {:b}", rating.value);
+        ratings.push(rating.value);
+    }
+    match product(&ratings[0], &ratings[1]) {
+        Some(result) => println!("life support rating {result}"),
+        None => println!("life support rating is too wide for a decimal number"),
+    }
 }
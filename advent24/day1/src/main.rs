@@ -1,86 +1,249 @@
 #![allow(dead_code)]
 use log::{debug, error, info};
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt::Display;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, prelude::*, BufReader};
+use std::str::FromStr;
 
-struct Pair {
-    first: u32,
-    second: u32,
-}
+// ordered by number, so the sorted column can be walked without the column itself
+type Histogram = BTreeMap<u32, u64>;
 
-impl Pair {
-    fn difference(&self) -> u32 {
-        self.first.abs_diff(self.second)
-    }
+struct Row {
+    values: Vec<u32>,
 }
 
-struct PairList {
-    pairs: Vec<Pair>,
+/// Rows of location ids, every row has the same number of columns
+struct ColumnList {
+    rows: Vec<Row>,
+    width: usize,
 }
 
-impl PairList {
-    fn new() -> PairList {
-        PairList { pairs: Vec::new() }
+impl ColumnList {
+    fn new(width: usize) -> ColumnList {
+        ColumnList {
+            rows: Vec::new(),
+            width,
+        }
+    }
+    fn add(&mut self, row: Row) {
+        self.rows.push(row);
     }
-    fn add(&mut self, pair: Pair) {
-        self.pairs.push(pair);
+    fn get_column(&self, column: usize) -> Vec<u32> {
+        self.rows.iter().map(|r| r.values[column]).collect()
+    }
+    fn get_column_sorted(&self, column: usize) -> Vec<u32> {
+        let mut sorted = self.get_column(column);
+        sorted.sort();
+        sorted
     }
-    fn from_vecs(firsts: Vec<u32>, seconds: Vec<u32>) -> PairList {
-        let mut pairs = Vec::new();
-        for i in 0..firsts.len() {
-            pairs.push(Pair {
-                first: firsts[i],
-                second: seconds[i],
-            });
+    /// How often each number occurs in the column
+    fn histogram(&self, column: usize) -> Histogram {
+        let mut histogram = BTreeMap::new();
+        for row in self.rows.iter() {
+            *histogram.entry(row.values[column]).or_insert(0) += 1;
         }
-        PairList { pairs }
+        histogram
     }
-    fn get_first(&self) -> Vec<u32> {
-        self.pairs.iter().map(|p| p.first).collect()
+    /// Sum of the differences after sorting both columns
+    fn sorted_distance(&self, a: usize, b: usize) -> u64 {
+        self.get_column_sorted(a)
+            .iter()
+            .zip(self.get_column_sorted(b).iter())
+            .map(|(x, y)| x.abs_diff(*y) as u64)
+            .sum()
     }
-    fn count_number_of_occurrences_on_right(&self, number: u32) -> u32 {
-        self.get_second().iter().filter(|&n| *n == number).count() as u32
+    fn evaluate(&self, query: &Query) -> MetricResult {
+        match query.metric {
+            Metric::Distance => MetricResult::Score(self.sorted_distance(query.a, query.b)),
+            _ => evaluate_histograms(
+                &self.histogram(query.a),
+                &self.histogram(query.b),
+                query.metric,
+            ),
+        }
     }
-    fn get_second(&self) -> Vec<u32> {
-        self.pairs.iter().map(|p| p.second).collect()
+    fn from_reader<R: BufRead>(reader: R) -> io::Result<ColumnList> {
+        let mut list = ColumnList::new(0);
+        list.width = for_each_row(reader, |row| list.add(row))?;
+        Ok(list)
     }
-    fn get_first_sorted(&self) -> Vec<u32> {
-        let mut sorted = self.get_first();
-        sorted.sort();
-        sorted
+}
+
+/// Each number on the left times how often it appears on the right
+fn similarity(left: &Histogram, right: &Histogram) -> u64 {
+    left.iter()
+        .map(|(number, count)| *number as u64 * count * right.get(number).unwrap_or(&0))
+        .sum()
+}
+
+/// Same as `ColumnList::sorted_distance`: both histograms are walked in order and the
+/// n-th smallest numbers are paired, a run of equal pairs at a time
+fn distance(left: &Histogram, right: &Histogram) -> u64 {
+    let mut left = left.iter().map(|(n, c)| (*n, *c));
+    let mut right = right.iter().map(|(n, c)| (*n, *c));
+    let (mut l, mut r) = (left.next(), right.next());
+    let mut total = 0;
+    while let (Some((a, count_a)), Some((b, count_b))) = (l, r) {
+        let pairs = count_a.min(count_b);
+        total += a.abs_diff(b) as u64 * pairs;
+        l = if count_a > pairs {
+            Some((a, count_a - pairs))
+        } else {
+            left.next()
+        };
+        r = if count_b > pairs {
+            Some((b, count_b - pairs))
+        } else {
+            right.next()
+        };
     }
-    fn get_second_sorted(&self) -> Vec<u32> {
-        let mut sorted = self.get_second();
-        sorted.sort();
-        sorted
+    total
+}
+
+/// Distinct numbers in both columns, sorted
+fn intersection(left: &Histogram, right: &Histogram) -> Vec<u32> {
+    left.keys()
+        .filter(|n| right.contains_key(n))
+        .copied()
+        .collect()
+}
+
+/// Distinct numbers in the left column that are missing on the right, sorted
+fn difference(left: &Histogram, right: &Histogram) -> Vec<u32> {
+    left.keys()
+        .filter(|n| !right.contains_key(n))
+        .copied()
+        .collect()
+}
+
+/// Every metric only needs the histograms of the two columns
+fn evaluate_histograms(left: &Histogram, right: &Histogram, metric: Metric) -> MetricResult {
+    match metric {
+        Metric::Distance => MetricResult::Score(distance(left, right)),
+        Metric::Similarity => MetricResult::Score(similarity(left, right)),
+        Metric::Intersection => MetricResult::Numbers(intersection(left, right)),
+        Metric::Difference => MetricResult::Numbers(difference(left, right)),
     }
-    fn get_all_sorted(&self) -> Self {
-        Self::from_vecs(self.get_first_sorted(), self.get_second_sorted())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Metric {
+    Distance,
+    Similarity,
+    Intersection,
+    Difference,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "distance" => Ok(Metric::Distance),
+            "similarity" => Ok(Metric::Similarity),
+            "intersection" => Ok(Metric::Intersection),
+            "difference" => Ok(Metric::Difference),
+            _ => Err(format!("unknown metric '{}'", s)),
+        }
     }
-    fn sum_of_differences(&self) -> u32 {
-        self.pairs.iter().map(|p| p.difference()).sum()
+}
+
+/// A metric between column `a` and column `b`
+struct Query {
+    metric: Metric,
+    a: usize,
+    b: usize,
+}
+
+impl Query {
+    /// Parses `<metric> <a> <b>` triples, defaults to the two puzzle parts on columns 0 and 1
+    fn parse_all(args: &[String]) -> Result<Vec<Query>, String> {
+        if args.is_empty() {
+            return Ok(vec![
+                Query {
+                    metric: Metric::Distance,
+                    a: 0,
+                    b: 1,
+                },
+                Query {
+                    metric: Metric::Similarity,
+                    a: 0,
+                    b: 1,
+                },
+            ]);
+        }
+        args.chunks(3)
+            .map(|chunk| match chunk {
+                [metric, a, b] => Ok(Query {
+                    metric: metric.parse()?,
+                    a: a.parse().map_err(|_| format!("invalid column '{}'", a))?,
+                    b: b.parse().map_err(|_| format!("invalid column '{}'", b))?,
+                }),
+                _ => Err(format!("expected <metric> <a> <b>, got {:?}", chunk)),
+            })
+            .collect()
     }
-    fn mult_count(&self) -> u32 {
-        let mut res: u32 = 0;
-        for pair in self.pairs.iter() {
-            let count = self.count_number_of_occurrences_on_right(pair.first);
-            res += pair.first * count;
+}
+
+enum MetricResult {
+    Score(u64),
+    Numbers(Vec<u32>),
+}
+
+impl Display for MetricResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetricResult::Score(score) => write!(f, "{}", score),
+            MetricResult::Numbers(numbers) => write!(f, "{} numbers {:?}", numbers.len(), numbers),
         }
-        res
     }
 }
 
-pub fn read_file(file_name: &str) -> std::io::Result<String> {
-    info!("Attempting to read file: {}", file_name);
-    let mut file = File::open(file_name)?;
-    let mut contents = String::new();
-    if let Err(e) = file.read_to_string(&mut contents) {
-        error!("Failed to read file: {}", e);
-        return Err(e);
+fn parse_row(line: &str) -> Option<Row> {
+    let values = line
+        .split_whitespace()
+        .map(|n| n.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    Some(Row { values })
+}
+
+/// Parses the list line by line without keeping the file in memory, returns the number of columns
+fn for_each_row<R: BufRead>(reader: R, mut f: impl FnMut(Row)) -> io::Result<usize> {
+    let mut width = None;
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}, got '{}'", n + 1, message, line),
+            )
+        };
+        let row = parse_row(&line).ok_or_else(|| invalid("expected numbers".to_string()))?;
+        let expected = *width.get_or_insert(row.values.len());
+        if row.values.len() != expected {
+            return Err(invalid(format!("expected {} numbers", expected)));
+        }
+        debug!("Row: {:?}", row.values);
+        f(row);
     }
-    info!("Successfully read file: {}", file_name);
-    Ok(contents)
+    Ok(width.unwrap_or(0))
+}
+
+/// Histograms of every column, memory only grows with the distinct numbers
+fn streaming_histograms<R: BufRead>(reader: R) -> io::Result<Vec<Histogram>> {
+    let mut histograms: Vec<Histogram> = Vec::new();
+    for_each_row(reader, |row| {
+        histograms.resize_with(row.values.len(), BTreeMap::new);
+        for (histogram, value) in histograms.iter_mut().zip(row.values) {
+            *histogram.entry(value).or_insert(0) += 1;
+        }
+    })?;
+    Ok(histograms)
 }
 
 fn main() {
@@ -88,30 +251,58 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
         error!("Please provide a filename as a command line argument.");
+        error!("Usage: {} <filename> [--stream] [<distance|similarity|intersection|difference> <column a> <column b>]...", args[0]);
         return;
     }
-    let contents = match read_file(&args[1]) {
-        Ok(contents) => contents,
+    let file = match File::open(&args[1]) {
+        Ok(file) => BufReader::new(file),
         Err(e) => {
             error!("Error reading file: {}", e);
             return;
         }
     };
-    let mut pairs = PairList::new();
-    for line in contents.lines() {
-        info!("Line: {}", line);
-        let splitline = line
-            .split_whitespace()
-            .map(|n| n.parse::<u32>().unwrap())
-            .collect::<Vec<u32>>();
-        let pair = Pair {
-            first: splitline[0],
-            second: splitline[1],
+    let stream = args.iter().any(|a| a == "--stream");
+    let query_args: Vec<String> = args[2..]
+        .iter()
+        .filter(|a| *a != "--stream")
+        .cloned()
+        .collect();
+    let queries = match Query::parse_all(&query_args) {
+        Ok(queries) => queries,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let (list, histograms) = if stream {
+        match streaming_histograms(file) {
+            Ok(histograms) => (None, histograms),
+            Err(e) => {
+                error!("Error reading file: {}", e);
+                return;
+            }
+        }
+    } else {
+        match ColumnList::from_reader(file) {
+            Ok(list) => (Some(list), Vec::new()),
+            Err(e) => {
+                error!("Error reading file: {}", e);
+                return;
+            }
+        }
+    };
+    let width = list.as_ref().map_or(histograms.len(), |l| l.width);
+    info!("Read {} columns", width);
+    for query in &queries {
+        let label = format!("{:?}({}, {})", query.metric, query.a, query.b);
+        if query.a >= width || query.b >= width {
+            error!("{}: the list only has {} columns", label, width);
+            continue;
+        }
+        let result = match &list {
+            Some(list) => list.evaluate(query),
+            None => evaluate_histograms(&histograms[query.a], &histograms[query.b], query.metric),
         };
-        debug!("Pair: ({}, {})", pair.first, pair.second);
-        pairs.add(pair);
+        println!("{}: {}", label, result);
     }
-    let sorted_pairs = pairs.get_all_sorted();
-    println!("Sum of differences: {}", sorted_pairs.sum_of_differences());
-    println!("Multiplication count: {}", pairs.mult_count());
 }
@@ -0,0 +1,255 @@
+//! Report validation shared by the `first` and `second` binaries
+use log::{debug, error, info};
+use std::fs::File;
+use std::io::prelude::*;
+use std::num::ParseIntError;
+
+pub fn read_file(file_name: &str) -> std::io::Result<String> {
+    info!("Attempting to read file: {}", file_name);
+    let mut file = File::open(file_name)?;
+    let mut contents = String::new();
+    if let Err(e) = file.read_to_string(&mut contents) {
+        error!("Failed to read file: {}", e);
+        return Err(e);
+    }
+    info!("Successfully read file: {}", file_name);
+    Ok(contents)
+}
+
+pub fn parse_report(line: &str) -> Result<Vec<i32>, ParseIntError> {
+    line.split_whitespace().map(|s| s.parse()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Violation {
+    StepTooLarge,
+    StepTooSmall,
+    EqualLevels,
+    DirectionChange,
+}
+
+impl Violation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Violation::StepTooLarge => "step_too_large",
+            Violation::StepTooSmall => "step_too_small",
+            Violation::EqualLevels => "equal_levels",
+            Violation::DirectionChange => "direction_change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    pub min_step: i32,
+    pub max_step: i32,
+    // number of levels that may be removed
+    pub tolerance: usize,
+}
+
+impl Rules {
+    pub const PART1: Rules = Rules {
+        min_step: 1,
+        max_step: 3,
+        tolerance: 0,
+    };
+    pub const PART2: Rules = Rules {
+        tolerance: 1,
+        ..Rules::PART1
+    };
+
+    // check a window given the direction so far
+    // The first entry window[0] is the previous number, the second window[1] is the current number
+    pub fn check(&self, direction: Direction, window: &[i32]) -> Result<Direction, Violation> {
+        let step = (window[1] - window[0]).abs();
+        if step > self.max_step {
+            debug!("Invalid difference: {:?}", window);
+            return Err(Violation::StepTooLarge);
+        }
+        if step == 0 {
+            debug!("Invalid same number: {:?}", window);
+            return Err(Violation::EqualLevels);
+        }
+        if step < self.min_step {
+            debug!("Invalid small difference: {:?}", window);
+            return Err(Violation::StepTooSmall);
+        }
+        let current = if window[0] < window[1] {
+            Direction::Up
+        } else {
+            Direction::Down
+        };
+        if direction != Direction::Unknown && direction != current {
+            debug!("Invalid direction change {:?}: {:?}", direction, window);
+            return Err(Violation::DirectionChange);
+        }
+        Ok(current)
+    }
+
+    fn step_ok(&self, from: i32, to: i32, direction: Direction) -> bool {
+        self.check(direction, &[from, to]).is_ok()
+    }
+}
+
+// The first window that breaks a rule, `index` is the position of its first level
+#[derive(Debug)]
+pub struct Offense {
+    pub index: usize,
+    pub levels: (i32, i32),
+    pub violation: Violation,
+}
+
+/// Validates the report without removing any level
+pub fn first_offense(numbers: &[i32], rules: &Rules) -> Result<Direction, Offense> {
+    let mut direction = Direction::Unknown;
+    for (index, window) in numbers.windows(2).enumerate() {
+        direction = rules
+            .check(direction, window)
+            .map_err(|violation| Offense {
+                index,
+                levels: (window[0], window[1]),
+                violation,
+            })?;
+    }
+    Ok(direction)
+}
+
+// Smallest number of removed levels that makes the report monotonic in one direction
+// dp[i] is the fewest removals so that the kept levels end with numbers[i].
+// At most `tolerance` levels are skipped between two kept ones, so this is O(n * tolerance)
+fn min_removals_in_direction(
+    numbers: &[i32],
+    rules: &Rules,
+    direction: Direction,
+) -> Option<usize> {
+    let n = numbers.len();
+    if n == 0 {
+        return Some(0);
+    }
+    let mut dp: Vec<Option<usize>> = vec![None; n];
+    for i in 0..n {
+        let mut best = (i <= rules.tolerance).then_some(i);
+        for j in i.saturating_sub(rules.tolerance + 1)..i {
+            if let Some(removed) = dp[j] {
+                if rules.step_ok(numbers[j], numbers[i], direction) {
+                    let removed = removed + i - j - 1;
+                    best = Some(best.map_or(removed, |b| b.min(removed)));
+                }
+            }
+        }
+        dp[i] = best.filter(|&removed| removed <= rules.tolerance);
+    }
+    (0..n)
+        .filter_map(|i| dp[i].map(|removed| removed + n - 1 - i))
+        .filter(|&removed| removed <= rules.tolerance)
+        .min()
+}
+
+/// Fewest levels to remove to make the report safe, `None` if more than `rules.tolerance`
+pub fn min_removals(numbers: &[i32], rules: &Rules) -> Option<usize> {
+    [Direction::Up, Direction::Down]
+        .iter()
+        .filter_map(|&direction| min_removals_in_direction(numbers, rules, direction))
+        .min()
+}
+
+pub fn is_safe(numbers: &[i32], rules: &Rules) -> bool {
+    min_removals(numbers, rules).is_some()
+}
+
+/// Number of safe reports for part 1 and part 2
+pub fn count_safe(reports: &[Vec<i32>], rules: &Rules) -> usize {
+    reports.iter().filter(|r| is_safe(r, rules)).count()
+}
+
+pub struct Diagnostic {
+    pub numbers: Vec<i32>,
+    pub direction: Direction,
+    pub offense: Option<Offense>,
+    // level whose removal makes an unsafe report safe
+    pub removal: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn new(numbers: Vec<i32>, rules: &Rules) -> Diagnostic {
+        match first_offense(&numbers, rules) {
+            Ok(direction) => Diagnostic {
+                numbers,
+                direction,
+                offense: None,
+                removal: None,
+            },
+            Err(offense) => {
+                // only the levels around the first offense or the one that set the direction can fix it
+                let removal = [
+                    offense.index.saturating_sub(1),
+                    offense.index,
+                    offense.index + 1,
+                ]
+                .into_iter()
+                .find(|&i| {
+                    let mut rest = numbers.clone();
+                    rest.remove(i);
+                    first_offense(&rest, rules).is_ok()
+                });
+                Diagnostic {
+                    numbers,
+                    direction: Direction::Unknown,
+                    offense: Some(offense),
+                    removal,
+                }
+            }
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let report = format!("{:?}", self.numbers);
+        match &self.offense {
+            None => format!("{} - safe ({:?})", report, self.direction),
+            Some(o) => {
+                let mut text = format!(
+                    "{} - unsafe: {} at levels {}..{} ({} -> {})",
+                    report,
+                    o.violation.name().replace('_', " "),
+                    o.index,
+                    o.index + 1,
+                    o.levels.0,
+                    o.levels.1
+                );
+                if let Some(i) = self.removal {
+                    text += &format!(", safe when removing level {} ({})", i, self.numbers[i]);
+                }
+                text
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"report\": {:?}, \"safe\": {}",
+            self.numbers,
+            self.offense.is_none()
+        );
+        if let Some(o) = &self.offense {
+            json += &format!(
+                ", \"window\": {}, \"levels\": [{}, {}], \"rule\": \"{}\", \"remove\": {}",
+                o.index,
+                o.levels.0,
+                o.levels.1,
+                o.violation.name(),
+                self.removal.map_or("null".to_string(), |i| i.to_string())
+            );
+        }
+        json + "}"
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,205 @@
+use super::*;
+use quickcheck::quickcheck;
+
+// The two historic validity checks of the `first` and `second` binaries, kept as reference
+mod historic {
+    #[derive(Debug, PartialEq)]
+    enum FirstDirection {
+        Up,
+        Down,
+        Unknown,
+        Invalid,
+    }
+
+    impl FirstDirection {
+        fn is_valid(&self, window: &[i32]) -> FirstDirection {
+            if (window[1] - window[0]).abs() > 3 {
+                return FirstDirection::Invalid;
+            }
+            if window[0] == window[1] {
+                return FirstDirection::Invalid;
+            }
+            match self {
+                FirstDirection::Down => {
+                    if window[0] < window[1] {
+                        FirstDirection::Invalid
+                    } else {
+                        FirstDirection::Down
+                    }
+                }
+                FirstDirection::Up => {
+                    if window[0] > window[1] {
+                        FirstDirection::Invalid
+                    } else {
+                        FirstDirection::Up
+                    }
+                }
+                FirstDirection::Unknown => {
+                    if window[0] < window[1] {
+                        FirstDirection::Up
+                    } else {
+                        FirstDirection::Down
+                    }
+                }
+                _ => FirstDirection::Invalid,
+            }
+        }
+    }
+
+    pub fn first(numbers: &[i32]) -> bool {
+        let mut direction = FirstDirection::Unknown;
+        numbers.windows(2).all(|window| {
+            direction = direction.is_valid(window);
+            direction != FirstDirection::Invalid
+        })
+    }
+
+    enum SecondDirection {
+        Up,
+        Down,
+        Unknown,
+    }
+
+    pub fn check_valid(numbers: &[i32]) -> bool {
+        let mut dir: SecondDirection = SecondDirection::Unknown;
+        for window in numbers.windows(2) {
+            if (window[1] - window[0]).abs() > 3 || window[0] == window[1] {
+                return false;
+            }
+            dir = match dir {
+                SecondDirection::Unknown => {
+                    if window[0] < window[1] {
+                        SecondDirection::Up
+                    } else {
+                        SecondDirection::Down
+                    }
+                }
+                SecondDirection::Up => {
+                    if window[0] < window[1] {
+                        SecondDirection::Up
+                    } else {
+                        return false;
+                    }
+                }
+                SecondDirection::Down => {
+                    if window[0] < window[1] {
+                        return false;
+                    } else {
+                        SecondDirection::Down
+                    }
+                }
+            };
+        }
+        true
+    }
+
+    pub fn check_valid_omit(numbers: &[i32]) -> bool {
+        for n in 0..numbers.len() {
+            let mut numbers_copy = numbers.to_owned();
+            numbers_copy.remove(n);
+            if check_valid(&numbers_copy) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// small levels so that safe reports and every kind of violation show up often
+fn report(levels: Vec<u8>) -> Vec<i32> {
+    levels
+        .into_iter()
+        .take(10)
+        .map(|l| (l % 12) as i32)
+        .collect()
+}
+
+// fewest removals by trying every subset of levels to keep
+fn brute_force_removals(numbers: &[i32], rules: &Rules) -> Option<usize> {
+    let strict = Rules {
+        tolerance: 0,
+        ..*rules
+    };
+    (0u32..1 << numbers.len())
+        .filter(|mask| {
+            let kept: Vec<i32> = (0..numbers.len())
+                .filter(|i| mask & (1 << i) != 0)
+                .map(|i| numbers[i])
+                .collect();
+            first_offense(&kept, &strict).is_ok()
+        })
+        .map(|mask| numbers.len() - mask.count_ones() as usize)
+        .min()
+        .filter(|&removed| removed <= rules.tolerance)
+}
+
+quickcheck! {
+    fn min_removals_matches_brute_force(levels: Vec<u8>, tolerance: u8) -> bool {
+        let numbers = report(levels);
+        // often at least as large as the report
+        let rules = Rules {
+            tolerance: tolerance as usize % 12,
+            ..Rules::PART1
+        };
+        min_removals(&numbers, &rules) == brute_force_removals(&numbers, &rules)
+    }
+
+    fn historic_checks_agree(levels: Vec<u8>) -> bool {
+        let numbers = report(levels);
+        historic::first(&numbers) == historic::check_valid(&numbers)
+    }
+
+    fn engine_matches_historic(levels: Vec<u8>) -> bool {
+        let numbers = report(levels);
+        is_safe(&numbers, &Rules::PART1) == historic::check_valid(&numbers)
+            && first_offense(&numbers, &Rules::PART1).is_ok() == historic::first(&numbers)
+    }
+
+    fn dampened_engine_matches_historic(levels: Vec<u8>) -> bool {
+        let numbers = report(levels);
+        is_safe(&numbers, &Rules::PART2)
+            == (historic::check_valid(&numbers) || historic::check_valid_omit(&numbers))
+    }
+
+    fn diagnostic_removal_is_safe(levels: Vec<u8>) -> bool {
+        let numbers = report(levels);
+        let diagnostic = Diagnostic::new(numbers.clone(), &Rules::PART1);
+        match diagnostic.removal {
+            Some(i) => {
+                let mut rest = numbers.clone();
+                rest.remove(i);
+                historic::check_valid(&rest)
+            }
+            None => diagnostic.offense.is_none() || !historic::check_valid_omit(&numbers),
+        }
+    }
+}
+
+#[test]
+fn test_example() {
+    let reports: Vec<Vec<i32>> = [
+        "7 6 4 2 1",
+        "1 2 7 8 9",
+        "9 7 6 2 1",
+        "1 3 2 4 5",
+        "8 6 4 4 1",
+        "1 3 6 7 9",
+    ]
+    .iter()
+    .map(|line| parse_report(line).unwrap())
+    .collect();
+    assert_eq!(count_safe(&reports, &Rules::PART1), 2);
+    assert_eq!(count_safe(&reports, &Rules::PART2), 4);
+}
+
+#[test]
+fn test_short_safe_reports_need_no_removal() {
+    let lenient = Rules {
+        tolerance: 5,
+        ..Rules::PART1
+    };
+    assert_eq!(min_removals(&[1, 2, 3], &lenient), Some(0));
+    assert_eq!(min_removals(&[1, 2], &Rules::PART2), Some(0));
+    assert_eq!(min_removals(&[1, 9], &Rules::PART2), Some(1));
+    assert_eq!(min_removals(&[], &Rules::PART1), Some(0));
+}
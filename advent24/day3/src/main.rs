@@ -1,20 +1,227 @@
 #![allow(dead_code)]
 use log::{debug, error, info};
-use regex::Regex;
 use std::env;
 use std::fs::File;
-use std::io::prelude::*;
-
-pub fn read_file(file_name: &str) -> std::io::Result<String> {
-    info!("Attempting to read file: {}", file_name);
-    let mut file = File::open(file_name)?;
-    let mut contents = String::new();
-    if let Err(e) = file.read_to_string(&mut contents) {
-        error!("Failed to read file: {}", e);
-        return Err(e);
+use std::io::{self, prelude::*, BufReader};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Mul(u64, u64),
+    Do,
+    Dont,
+}
+
+// An instruction looks like `name(arg,arg,...)`, every argument has 1 to `max_digits` digits
+struct InstructionSpec {
+    name: &'static [u8],
+    arity: usize,
+    build: fn(&[u64]) -> Token,
+}
+
+// New instructions get a `Token` variant, an entry here and an arm in `Interpreter::execute`
+const REGISTRY: &[InstructionSpec] = &[
+    InstructionSpec {
+        name: b"mul",
+        arity: 2,
+        build: |args| Token::Mul(args[0], args[1]),
+    },
+    InstructionSpec {
+        name: b"do",
+        arity: 0,
+        build: |_| Token::Do,
+    },
+    InstructionSpec {
+        name: b"don't",
+        arity: 0,
+        build: |_| Token::Dont,
+    },
+];
+
+const MAX_DIGITS: usize = 3;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq)]
+enum Scan {
+    // token and its length in bytes
+    Token(Token, usize),
+    NoMatch,
+    // the input ends inside what could still become a token
+    Incomplete,
+}
+
+// Matches an instruction at the start of `rest`
+fn match_at(rest: &[u8], registry: &[InstructionSpec], max_digits: usize) -> Scan {
+    let mut incomplete = false;
+    for spec in registry {
+        match match_spec(rest, spec, max_digits) {
+            Scan::Token(token, len) => return Scan::Token(token, len),
+            Scan::Incomplete => incomplete = true,
+            Scan::NoMatch => {}
+        }
+    }
+    if incomplete {
+        Scan::Incomplete
+    } else {
+        Scan::NoMatch
+    }
+}
+
+fn match_spec(rest: &[u8], spec: &InstructionSpec, max_digits: usize) -> Scan {
+    // checks the next byte, running out of input is not a mismatch
+    macro_rules! expect {
+        ($i:expr, $c:expr) => {
+            match rest.get($i) {
+                None => return Scan::Incomplete,
+                Some(c) if *c != $c => return Scan::NoMatch,
+                _ => {}
+            }
+        };
+    }
+    for (i, c) in spec.name.iter().enumerate() {
+        expect!(i, *c);
+    }
+    let mut i = spec.name.len();
+    expect!(i, b'(');
+    i += 1;
+    let mut args = Vec::with_capacity(spec.arity);
+    for n in 0..spec.arity {
+        if n > 0 {
+            expect!(i, b',');
+            i += 1;
+        }
+        let digits = rest[i..]
+            .iter()
+            .take(max_digits + 1)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits <= max_digits && i + digits == rest.len() {
+            return Scan::Incomplete;
+        }
+        if digits == 0 || digits > max_digits {
+            return Scan::NoMatch;
+        }
+        let number = rest[i..i + digits]
+            .iter()
+            .fold(0, |acc, c| acc * 10 + (c - b'0') as u64);
+        args.push(number);
+        i += digits;
+    }
+    expect!(i, b')');
+    Scan::Token((spec.build)(&args), i + 1)
+}
+
+// Scans the input in fixed-size chunks. Only the unfinished tail of a chunk is kept,
+// so memory stays at one chunk plus the longest instruction
+struct Tokenizer<R: BufRead> {
+    reader: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    chunk_size: usize,
+    registry: &'static [InstructionSpec],
+    max_digits: usize,
+}
+
+impl<R: BufRead> Tokenizer<R> {
+    fn new(reader: R) -> Tokenizer<R> {
+        Tokenizer {
+            reader,
+            buffer: Vec::new(),
+            pos: 0,
+            eof: false,
+            chunk_size: CHUNK_SIZE,
+            registry: REGISTRY,
+            max_digits: MAX_DIGITS,
+        }
+    }
+
+    fn with_chunk_size(mut self, chunk_size: usize) -> Tokenizer<R> {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        let old = self.buffer.len();
+        self.buffer.resize(old + self.chunk_size, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buffer[old..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let n = read.inspect_err(|_| self.buffer.truncate(old))?;
+        self.buffer.truncate(old + n);
+        self.eof = n == 0;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Tokenizer<R> {
+    type Item = io::Result<Token>;
+
+    fn next(&mut self) -> Option<io::Result<Token>> {
+        loop {
+            while self.pos < self.buffer.len() {
+                match match_at(&self.buffer[self.pos..], self.registry, self.max_digits) {
+                    Scan::Token(token, len) => {
+                        self.pos += len;
+                        debug!("Found: {:?}", token);
+                        return Some(Ok(token));
+                    }
+                    Scan::Incomplete if !self.eof => break,
+                    _ => self.pos += 1,
+                }
+            }
+            if self.eof {
+                return None;
+            }
+            if let Err(e) = self.read_chunk() {
+                self.eof = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+// Runs the token stream once and computes both parts
+struct Interpreter {
+    enabled: bool,
+    // sum of all multiplications, ignoring do() and don't()
+    total: u64,
+    // sum of the multiplications while enabled
+    enabled_total: u64,
+}
+
+impl Interpreter {
+    fn new() -> Interpreter {
+        Interpreter {
+            enabled: true,
+            total: 0,
+            enabled_total: 0,
+        }
+    }
+
+    fn execute(&mut self, token: Token) {
+        match token {
+            Token::Mul(a, b) => {
+                self.total += a * b;
+                if self.enabled {
+                    self.enabled_total += a * b;
+                }
+            }
+            Token::Do => self.enabled = true,
+            Token::Dont => self.enabled = false,
+        }
+    }
+
+    fn run(&mut self, tokens: impl Iterator<Item = Token>) {
+        for token in tokens {
+            self.execute(token);
+        }
     }
-    info!("Successfully read file: {}", file_name);
-    Ok(contents)
 }
 
 fn main() {
@@ -24,71 +231,36 @@ fn main() {
         error!("Please provide a filename as a command line argument.");
         return;
     }
-    let contents = match read_file(&args[1]) {
-        Ok(contents) => contents,
+    let file = match File::open(&args[1]) {
+        Ok(file) => BufReader::new(file),
         Err(e) => {
             error!("Error reading file: {}", e);
             return;
         }
     };
-    debug!("File contents: {}", contents);
-
-    let mut result: u64 = 0;
-    let re = Regex::new(r"mul\((\d+),\s*(\d+)\)").unwrap();
-    for cap in re.captures_iter(&contents) {
-        let num1: u64 = cap[1].parse().unwrap();
-        let num2: u64 = cap[2].parse().unwrap();
-        result += num1 * num2;
-        debug!("Found: {} * {} = {}", num1, num2, num1 * num2);
-    }
-    println!("Total sum of all multiplications: {}", result);
-
-    let mut result: u64 = 0;
-    let chars: Vec<char> = contents.chars().collect();
-    let mut i = 0;
-    let mut activated = true;
-    while i < chars.len() {
-        if chars[i..].starts_with(&['d', 'o', '(', ')']) {
-            debug!("Found: do()");
-            activated = true;
-            i += 4;
-        } else if chars[i..].starts_with(&['d', 'o', 'n', '\'', 't', '(', ')']) {
-            debug!("Found: don't()");
-            activated = false;
-            i += 7;
-        } else if chars[i..].starts_with(&['m', 'u', 'l', '(']) {
-            if !activated {
-                i += 4;
-                continue;
-            }
-            i += 4; // Skip "mul("
-            let mut num1 = String::new();
-            while chars[i].is_ascii_digit() {
-                num1.push(chars[i]);
-                i += 1;
-            }
-            if chars[i] == ',' {
-                i += 1;
-            } else {
-                continue;
-            }
-            let mut num2 = String::new();
-            while chars[i].is_ascii_digit() {
-                num2.push(chars[i]);
-                i += 1;
-            }
-            if chars[i] == ')' {
-                i += 1; // Skip ")"
-            } else {
-                continue;
+    // optional chunk size in bytes, mostly to exercise tokens split across chunks
+    let chunk_size: usize = args
+        .get(2)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(CHUNK_SIZE);
+    info!("Scanning {} in chunks of {} bytes", args[1], chunk_size);
+
+    let mut interpreter = Interpreter::new();
+    for token in Tokenizer::new(file).with_chunk_size(chunk_size) {
+        match token {
+            Ok(token) => interpreter.execute(token),
+            Err(e) => {
+                error!("Error reading file: {}", e);
+                return;
             }
-            let number1: u64 = num1.parse().unwrap();
-            let number2: u64 = num2.parse().unwrap();
-            debug!("Found: mul({}, {})", number1, number2);
-            result += number1 * number2;
-        } else {
-            i += 1;
         }
     }
-    println!("Total sum of all activated multiplications: {}", result);
+    println!("Total sum of all multiplications: {}", interpreter.total);
+    println!(
+        "Total sum of all activated multiplications: {}",
+        interpreter.enabled_total
+    );
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,134 @@
+use super::*;
+
+const PART1: &str = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+const PART2: &str = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+
+fn tokens(input: &str, chunk_size: usize) -> Vec<Token> {
+    Tokenizer::new(input.as_bytes())
+        .with_chunk_size(chunk_size)
+        .map(|token| token.unwrap())
+        .collect()
+}
+
+// every small chunk size splits some token, the input length reads it in one go
+fn chunk_sizes(input: &str) -> impl Iterator<Item = usize> {
+    (1..=8).chain([input.len()])
+}
+
+#[test]
+fn test_examples_in_chunks() {
+    for chunk_size in chunk_sizes(PART1) {
+        let mut interpreter = Interpreter::new();
+        interpreter.run(tokens(PART1, chunk_size).into_iter());
+        assert_eq!(interpreter.total, 161, "chunk size {}", chunk_size);
+    }
+    for chunk_size in chunk_sizes(PART2) {
+        let mut interpreter = Interpreter::new();
+        interpreter.run(tokens(PART2, chunk_size).into_iter());
+        assert_eq!(interpreter.enabled_total, 48, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn test_chunks_give_the_same_tokens() {
+    for input in [PART1, PART2] {
+        let whole = tokens(input, input.len());
+        for chunk_size in 1..=8 {
+            assert_eq!(
+                tokens(input, chunk_size),
+                whole,
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+}
+
+#[test]
+fn test_truncated_mul_at_eof() {
+    let input = "mul(2,3)mul(12,";
+    for chunk_size in chunk_sizes(input) {
+        assert_eq!(tokens(input, chunk_size), vec![Token::Mul(2, 3)]);
+    }
+}
+
+#[test]
+fn test_truncated_dont_at_eof() {
+    let input = "mul(2,3)don't(";
+    for chunk_size in chunk_sizes(input) {
+        assert_eq!(tokens(input, chunk_size), vec![Token::Mul(2, 3)]);
+    }
+}
+
+#[test]
+fn test_unfinished_instruction_does_not_hide_the_next() {
+    let input = "don't(mul(4,5)mul(6,";
+    for chunk_size in chunk_sizes(input) {
+        assert_eq!(tokens(input, chunk_size), vec![Token::Mul(4, 5)]);
+    }
+}
+
+fn spec(name: &[u8]) -> &'static InstructionSpec {
+    REGISTRY.iter().find(|s| s.name == name).unwrap()
+}
+
+#[test]
+fn test_match_spec() {
+    let mul = spec(b"mul");
+    assert_eq!(
+        match_spec(b"mul(1,234)x", mul, MAX_DIGITS),
+        Scan::Token(Token::Mul(1, 234), 10)
+    );
+    for rest in [
+        &b"mu"[..],
+        b"mul",
+        b"mul(",
+        b"mul(12",
+        b"mul(12,",
+        b"mul(12,3",
+    ] {
+        assert_eq!(match_spec(rest, mul, MAX_DIGITS), Scan::Incomplete);
+    }
+    for rest in [
+        &b"mux"[..],
+        b"mul[1,2]",
+        b"mul()",
+        b"mul(1234,5)",
+        b"mul(1,2]",
+        b"mul(1 ,2)",
+    ] {
+        assert_eq!(match_spec(rest, mul, MAX_DIGITS), Scan::NoMatch);
+    }
+    assert_eq!(
+        match_spec(b"do()", spec(b"do"), MAX_DIGITS),
+        Scan::Token(Token::Do, 4)
+    );
+}
+
+#[test]
+fn test_registry_tries_every_instruction() {
+    // `do` could still become `do()` or `don't()`
+    assert_eq!(match_at(b"do", REGISTRY, MAX_DIGITS), Scan::Incomplete);
+    assert_eq!(match_at(b"don", REGISTRY, MAX_DIGITS), Scan::Incomplete);
+    assert_eq!(
+        match_at(b"don't()", REGISTRY, MAX_DIGITS),
+        Scan::Token(Token::Dont, 7)
+    );
+    assert_eq!(match_at(b"dox", REGISTRY, MAX_DIGITS), Scan::NoMatch);
+    assert_eq!(match_at(b"", REGISTRY, MAX_DIGITS), Scan::Incomplete);
+}
+
+#[test]
+fn test_custom_registry() {
+    const SUM: &[InstructionSpec] = &[InstructionSpec {
+        name: b"sum",
+        arity: 3,
+        build: |args| Token::Mul(args[0] + args[1] + args[2], 1),
+    }];
+    assert_eq!(
+        match_at(b"sum(1,22,3)", SUM, 2),
+        Scan::Token(Token::Mul(26, 1), 11)
+    );
+    assert_eq!(match_at(b"sum(1,222,3)", SUM, 2), Scan::NoMatch);
+    assert_eq!(match_at(b"mul(1,2)", SUM, 2), Scan::NoMatch);
+}
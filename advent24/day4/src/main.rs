@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 use log::{debug, error, info};
+use std::collections::HashSet;
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
@@ -28,6 +29,134 @@ pub fn read_file(file_name: &str) -> std::io::Result<String> {
     Ok(contents)
 }
 
+// Matches any character, in searched words as well as in templates
+const WILDCARD: char = '.';
+
+// (row, col) steps: right, down-right, down, down-left, left, up-left, up, up-right
+const DIRECTIONS: [(isize, isize); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct WordMatch {
+    // index into the searched words
+    word: usize,
+    row: usize,
+    col: usize,
+    direction: (isize, isize),
+    len: usize,
+}
+
+impl WordMatch {
+    // Position of the last letter
+    fn end(&self) -> (usize, usize) {
+        let last = self.len.saturating_sub(1) as isize;
+        (
+            (self.row as isize + last * self.direction.0) as usize,
+            (self.col as isize + last * self.direction.1) as usize,
+        )
+    }
+
+    // Positions of all letters of the match
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.len as isize).map(|i| {
+            (
+                (self.row as isize + i * self.direction.0) as usize,
+                (self.col as isize + i * self.direction.1) as usize,
+            )
+        })
+    }
+}
+
+// A small 2D pattern, rows separated by `/`, `.` matches any character
+#[derive(Debug, Clone, PartialEq)]
+struct Template {
+    rows: Vec<Vec<char>>,
+}
+
+impl Template {
+    fn parse(pattern: &str) -> Result<Template, String> {
+        let rows: Vec<Vec<char>> = pattern.split('/').map(|r| r.chars().collect()).collect();
+        if rows[0].is_empty() || rows.iter().any(|r| r.len() != rows[0].len()) {
+            return Err(format!(
+                "Template rows must be non-empty and equally long: {}",
+                pattern
+            ));
+        }
+        Ok(Template { rows })
+    }
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+    fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+    // Turned 90 degrees clockwise
+    fn rotate(&self) -> Template {
+        let rows = (0..self.width())
+            .map(|col| {
+                (0..self.height())
+                    .rev()
+                    .map(|row| self.rows[row][col])
+                    .collect()
+            })
+            .collect();
+        Template { rows }
+    }
+    // Mirrored left to right
+    fn reflect(&self) -> Template {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect();
+        Template { rows }
+    }
+    // The distinct rotations and reflections, the template itself first
+    fn variants(&self) -> Vec<Template> {
+        let mut variants: Vec<Template> = Vec::new();
+        for start in [self.clone(), self.reflect()] {
+            let mut t = start;
+            for _ in 0..4 {
+                let next = t.rotate();
+                if !variants.contains(&t) {
+                    variants.push(t);
+                }
+                t = next;
+            }
+        }
+        variants
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TemplateMatch {
+    row: usize,
+    col: usize,
+    // index into `Template::variants`
+    variant: usize,
+}
+
+const ANSI_COLORS: [u8; 12] = [31, 32, 33, 34, 35, 36, 91, 92, 93, 94, 95, 96];
+const HTML_COLORS: [&str; 12] = [
+    "#d62728", "#2ca02c", "#ff7f0e", "#1f77b4", "#9467bd", "#17becf", "#e377c2", "#8c564b",
+    "#bcbd22", "#7f7f7f", "#393b79", "#637939",
+];
+
+// Whether each match or each searched word gets its own colour
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorBy {
+    Match,
+    Word,
+}
+
 struct Matrix {
     rows: Vec<Vec<char>>,
 }
@@ -73,120 +202,171 @@ impl Matrix {
         }
         column
     }
-    fn columns(&self) -> Vec<Vec<char>> {
-        let mut columns: Vec<Vec<char>> = Vec::new();
-        for i in 0..self.width() {
-            columns.push(self.get_column(i));
-        }
-        columns
-    }
-    fn diagonals(&self) -> Vec<Vec<char>> {
-        let mut diagonals: Vec<Vec<char>> = Vec::new();
-        for i in 0..self.width() {
-            let mut diagonal: Vec<char> = Vec::new();
-            let mut j = 0;
-            let mut k = i;
-            while j < self.height() && k < self.width() {
-                diagonal.push(self.rows[j][k]);
-                j += 1;
-                k += 1;
-            }
-            diagonals.push(diagonal);
-        }
-        for i in 1..self.height() {
-            let mut diagonal: Vec<char> = Vec::new();
-            let mut j = i;
-            let mut k = 0;
-            while j < self.height() && k < self.width() {
-                diagonal.push(self.rows[j][k]);
-                j += 1;
-                k += 1;
-            }
-            diagonals.push(diagonal);
-        }
-
-        // Top-right to bottom-left diagonals
-        for i in 0..self.width() {
-            let mut diagonal: Vec<char> = Vec::new();
-            let mut j = 0;
-            let mut k = i;
-            while j < self.height() && k < self.width() {
-                diagonal.push(self.rows[j][self.width() - 1 - k]);
-                j += 1;
-                k += 1;
-            }
-            diagonals.push(diagonal);
-        }
-        for i in 1..self.height() {
-            let mut diagonal: Vec<char> = Vec::new();
-            let mut j = i;
-            let mut k = 0;
-            while j < self.height() && k < self.width() {
-                diagonal.push(self.rows[j][self.width() - 1 - k]);
-                j += 1;
-                k += 1;
+    fn get(&self, row: isize, col: isize) -> Option<char> {
+        if row < 0 || col < 0 {
+            return None;
+        }
+        self.rows.get(row as usize)?.get(col as usize).copied()
+    }
+
+    // Does `word` start at (row, col) reading in direction (drow, dcol)
+    fn matches_at(
+        &self,
+        word: &[char],
+        row: usize,
+        col: usize,
+        (drow, dcol): (isize, isize),
+    ) -> bool {
+        word.iter().enumerate().all(|(i, &w)| {
+            let i = i as isize;
+            self.get(row as isize + i * drow, col as isize + i * dcol)
+                .is_some_and(|c| w == WILDCARD || c == w)
+        })
+    }
+
+    // Finds every word in all eight directions, `.` in a word matches any character.
+    // A word that reads the same backwards (or has a single letter) is found from both ends
+    // of the same cells, only the first of those matches is kept
+    fn find_words(&self, words: &[&str]) -> Vec<WordMatch> {
+        let words: Vec<Vec<char>> = words.iter().map(|w| w.chars().collect()).collect();
+        let mut found = Vec::new();
+        let mut spans = HashSet::new();
+        for row in 0..self.height() {
+            for col in 0..self.width() {
+                for (index, word) in words.iter().enumerate() {
+                    if word.is_empty() || (word[0] != WILDCARD && word[0] != self.rows[row][col]) {
+                        continue;
+                    }
+                    for direction in DIRECTIONS {
+                        if !self.matches_at(word, row, col, direction) {
+                            continue;
+                        }
+                        let m = WordMatch {
+                            word: index,
+                            row,
+                            col,
+                            direction,
+                            len: word.len(),
+                        };
+                        let (start, end) = ((row, col), m.end());
+                        if spans.insert((index, start.min(end), start.max(end))) {
+                            found.push(m);
+                        }
+                    }
+                }
             }
-            diagonals.push(diagonal);
         }
-        diagonals
+        found
     }
+
     // Count the number of times the string "XMAS" appears in the matrix
     fn count_xmas(&self) -> u64 {
-        let mut count: u64 = 0;
-        for row in self.rows.iter() {
-            count += row.iter().collect::<String>().matches("XMAS").count() as u64;
-            count += row.iter().collect::<String>().matches("SAMX").count() as u64;
-        }
-        for col in self.columns().iter() {
-            count += col.iter().collect::<String>().matches("XMAS").count() as u64;
-            count += col.iter().collect::<String>().matches("SAMX").count() as u64;
-        }
-        debug!("Diagonals: {:?}", self.diagonals());
-        for col in self.diagonals().iter() {
-            count += col.iter().collect::<String>().matches("XMAS").count() as u64;
-            count += col.iter().collect::<String>().matches("SAMX").count() as u64;
-        }
-        count
-    }
-
-    // Get a slide of the matrix of the shape 3x3 at the specified row and column
-    // If the row or column is at the edge of the matrix, return None
-    fn get_3x3_slice(&self, row: usize, col: usize) -> Option<Vec<Vec<char>>> {
-        let mut slice: Vec<Vec<char>> = Vec::new();
-        for i in 0..3 {
-            let mut row_slice: Vec<char> = Vec::new();
-            for j in 0..3 {
-                if row + i >= self.height() || col + j >= self.width() {
-                    return None;
+        self.find_words(&["XMAS"]).len() as u64
+    }
+
+    // Does the template fit with its top-left corner at (row, col)
+    fn template_at(&self, template: &Template, row: usize, col: usize) -> bool {
+        row + template.height() <= self.height()
+            && col + template.width() <= self.width()
+            && template.rows.iter().enumerate().all(|(i, trow)| {
+                trow.iter()
+                    .enumerate()
+                    .all(|(j, &t)| t == WILDCARD || self.rows[row + i][col + j] == t)
+            })
+    }
+
+    // Anchors (top-left corners) of all template matches, optionally under all rotations
+    // and reflections of the template
+    fn find_template(&self, template: &Template, symmetric: bool) -> Vec<TemplateMatch> {
+        let variants = if symmetric {
+            template.variants()
+        } else {
+            vec![template.clone()]
+        };
+        let mut found = Vec::new();
+        for row in 0..self.height() {
+            for col in 0..self.width() {
+                for (variant, t) in variants.iter().enumerate() {
+                    if self.template_at(t, row, col) {
+                        found.push(TemplateMatch { row, col, variant });
+                    }
                 }
-                row_slice.push(self.rows[row + i][col + j]);
             }
-            slice.push(row_slice);
         }
-        Some(slice)
+        found
     }
 
-    fn check_x_mas(&self, mat: &[Vec<char>]) -> bool {
-        if mat[1][1] != 'A' {
-            return false;
+    fn count_x_mas(&self) -> u32 {
+        let x_mas = Template::parse("M.S/.A./M.S").unwrap();
+        let found = self.find_template(&x_mas, true);
+        for m in found.iter() {
+            debug!("{:?}x{}", m.row, m.col);
         }
-        ((mat[0][0] == 'M' && mat[2][2] == 'S') || (mat[0][0] == 'S' && mat[2][2] == 'M'))
-            && ((mat[2][0] == 'M' && mat[0][2] == 'S') || (mat[2][0] == 'S' && mat[0][2] == 'M'))
+        found.len() as u32
     }
 
-    fn count_x_mas(&self) -> u32 {
-        let mut count: u32 = 0;
-        for i in 0..self.height() {
-            for j in 0..self.width() {
-                if let Some(slice) = self.get_3x3_slice(i, j) {
-                    if self.check_x_mas(&slice) {
-                        debug!("{:?}x{}", i, j);
-                        count += 1;
+    // Colour index per cell, `None` for cells outside every match. Later matches win.
+    fn highlight(&self, matches: &[WordMatch], color_by: ColorBy) -> Vec<Vec<Option<usize>>> {
+        let mut colors = vec![vec![None; self.width()]; self.height()];
+        for (i, m) in matches.iter().enumerate() {
+            let color = match color_by {
+                ColorBy::Match => i,
+                ColorBy::Word => m.word,
+            };
+            for (row, col) in m.cells() {
+                colors[row][col] = Some(color);
+            }
+        }
+        colors
+    }
+
+    // Matches in colour, everything else dimmed
+    fn render_ansi(&self, matches: &[WordMatch], color_by: ColorBy) -> String {
+        let mut out = String::new();
+        for (row, colors) in self.rows.iter().zip(self.highlight(matches, color_by)) {
+            for (c, color) in row.iter().zip(colors) {
+                match color {
+                    Some(color) => {
+                        out += &format!(
+                            "\x1b[1;{}m{}\x1b[0m",
+                            ANSI_COLORS[color % ANSI_COLORS.len()],
+                            c
+                        )
                     }
+                    None => out += &format!("\x1b[2m{}\x1b[0m", c),
                 }
             }
+            out.push('\n');
         }
-        count
+        out
+    }
+
+    fn render_html(&self, matches: &[WordMatch], color_by: ColorBy) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<body>\n<pre style=\"font-family: monospace; color: #bbb\">\n",
+        );
+        for (row, colors) in self.rows.iter().zip(self.highlight(matches, color_by)) {
+            for (c, color) in row.iter().zip(colors) {
+                let c = match c {
+                    '<' => "&lt;".to_string(),
+                    '>' => "&gt;".to_string(),
+                    '&' => "&amp;".to_string(),
+                    c => c.to_string(),
+                };
+                match color {
+                    Some(color) => {
+                        out += &format!(
+                            "<b style=\"color: {}\">{}</b>",
+                            HTML_COLORS[color % HTML_COLORS.len()],
+                            c
+                        )
+                    }
+                    None => out += &c,
+                }
+            }
+            out.push('\n');
+        }
+        out + "</pre>\n</body>\n</html>\n"
     }
 
     fn display(&self, replace: Vec<char>) {
@@ -221,7 +401,67 @@ fn main() {
     debug!("File contents: {}", contents);
     let m = Matrix::from_string(&contents);
     println!("XMAS appears {} times in the matrix", m.count_xmas());
-    debug!("{:?}", m.get_3x3_slice(4, 4));
+    // further arguments are words to search for, or `--template M.S/.A./M.S` with
+    // `--symmetric` to also match rotations and reflections.
+    // `--highlight` prints the found words in colour (`--by-word` for one colour per word),
+    // `--html FILE` writes the same as HTML
+    let mut words: Vec<&str> = Vec::new();
+    let mut template = None;
+    let mut symmetric = false;
+    let mut highlight = false;
+    let mut color_by = ColorBy::Match;
+    let mut html = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--template" => template = rest.next(),
+            "--symmetric" => symmetric = true,
+            "--highlight" => highlight = true,
+            "--by-word" => color_by = ColorBy::Word,
+            "--html" => html = rest.next(),
+            word => words.push(word),
+        }
+    }
+    if !words.is_empty() {
+        for found in m.find_words(&words) {
+            println!(
+                "{} at ({}, {}) direction {:?}",
+                words[found.word], found.row, found.col, found.direction
+            );
+        }
+    }
+    if highlight || html.is_some() {
+        if words.is_empty() {
+            words.push("XMAS");
+        }
+        let found = m.find_words(&words);
+        if highlight {
+            print!("{}", m.render_ansi(&found, color_by));
+        }
+        if let Some(file_name) = html {
+            match File::create(file_name)
+                .and_then(|mut f| f.write_all(m.render_html(&found, color_by).as_bytes()))
+            {
+                Ok(()) => info!("Wrote {}", file_name),
+                Err(e) => error!("Could not write {}: {}", file_name, e),
+            }
+        }
+    }
+    if let Some(pattern) = template {
+        match Template::parse(pattern) {
+            Ok(t) => {
+                let found = m.find_template(&t, symmetric);
+                for f in found.iter() {
+                    println!(
+                        "{} at ({}, {}) variant {}",
+                        pattern, f.row, f.col, f.variant
+                    );
+                }
+                println!("{} appears {} times", pattern, found.len());
+            }
+            Err(e) => error!("{}", e),
+        }
+    }
     m.display(vec!['X']);
     println!(
         "XMAS appears {} times in 3x3 slices of the matrix",
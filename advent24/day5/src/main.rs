@@ -1,7 +1,8 @@
 #![allow(dead_code)]
 use log::{debug, error, info};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
+use std::fmt::Display;
 use std::fs::File;
 use std::io::prelude::*;
 use std::time::Instant;
@@ -18,33 +19,364 @@ pub fn read_file(file_name: &str) -> std::io::Result<String> {
     Ok(contents)
 }
 
+/// Adjacency matrix of the rules between the pages they name, row `x` has bit `y` set for
+/// every rule `x|y`. Pages are numbered densely so the matrix grows with the page count,
+/// not with the largest page id
+struct RuleSet {
+    // dense number of every page in a rule
+    index: HashMap<u16, usize>,
+    // u64 words per row
+    stride: usize,
+    bits: Vec<u64>,
+}
+
+impl RuleSet {
+    fn new(rules: &[(u16, u16)]) -> RuleSet {
+        let mut index = HashMap::new();
+        for &(x, y) in rules {
+            for page in [x, y] {
+                let next = index.len();
+                index.entry(page).or_insert(next);
+            }
+        }
+        let stride = index.len().div_ceil(64);
+        let mut bits = vec![0; index.len() * stride];
+        for &(x, y) in rules {
+            let y = index[&y];
+            bits[index[&x] * stride + y / 64] |= 1 << (y % 64);
+        }
+        RuleSet {
+            index,
+            stride,
+            bits,
+        }
+    }
+
+    // pages without rules have an empty row
+    fn row(&self, page: u16) -> &[u64] {
+        match self.index.get(&page) {
+            Some(&i) => &self.bits[i * self.stride..(i + 1) * self.stride],
+            None => &[],
+        }
+    }
+
+    fn contains(&self, before: u16, after: u16) -> bool {
+        self.index.get(&after).is_some_and(|&after| {
+            self.row(before)
+                .get(after / 64)
+                .is_some_and(|word| word & (1 << (after % 64)) != 0)
+        })
+    }
+
+    /// One pass over the update: a page is misplaced if a rule wants it before a page already seen
+    fn is_valid(&self, seq: &[u16]) -> bool {
+        let mut seen = vec![0u64; self.stride];
+        for &page in seq {
+            let Some(&i) = self.index.get(&page) else {
+                continue;
+            };
+            let row = &self.bits[i * self.stride..(i + 1) * self.stride];
+            if row.iter().zip(seen.iter()).any(|(r, s)| r & s != 0) {
+                return false;
+            }
+            seen[i / 64] |= 1 << (i % 64);
+        }
+        true
+    }
+}
+
 trait ComesBefore {
-    fn comes_before(&self, mapper: &HashMap<u16, Vec<u16>>, right: u16) -> bool;
+    fn comes_before(&self, rules: &RuleSet, right: u16) -> bool;
 }
 
 impl ComesBefore for u16 {
-    fn comes_before(&self, mapper: &HashMap<u16, Vec<u16>>, right: u16) -> bool {
-        if let Some(allowed) = mapper.get(self) {
-            return allowed.contains(&right);
+    fn comes_before(&self, rules: &RuleSet, right: u16) -> bool {
+        rules.contains(*self, right)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum OrderError {
+    // the pages that are left once no page is free of predecessors
+    Cycle(Vec<u16>),
+}
+
+impl Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::Cycle(pages) => write!(f, "rules form a cycle between pages {:?}", pages),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct TopoOrder {
+    pages: Vec<u16>,
+    // more than one page was free to go next at some point, so other orders are valid too
+    ambiguous: bool,
+}
+
+// Kahn's algorithm on the rules between the pages of one update.
+// Pages that are free at the same time keep their order from the update
+fn topo_sort(seq: &[u16], rules: &RuleSet) -> Result<TopoOrder, OrderError> {
+    let n = seq.len();
+    let mut successors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for (i, a) in seq.iter().enumerate() {
+        for (j, b) in seq.iter().enumerate() {
+            if i != j && a.comes_before(rules, *b) {
+                successors[i].push(j);
+            }
+        }
+    }
+    kahn(seq, &successors)
+}
+
+// `successors[i]` are the positions in `seq` that have to come after position i
+fn kahn(seq: &[u16], successors: &[Vec<usize>]) -> Result<TopoOrder, OrderError> {
+    let n = seq.len();
+    let mut indegree = vec![0; n];
+    for &j in successors.iter().flatten() {
+        indegree[j] += 1;
+    }
+    let mut ready: BTreeSet<usize> = (0..n).filter(|&i| indegree[i] == 0).collect();
+    let mut pages = Vec::with_capacity(n);
+    let mut ambiguous = false;
+    while let Some(i) = ready.pop_first() {
+        ambiguous |= !ready.is_empty();
+        pages.push(seq[i]);
+        for &j in successors[i].iter() {
+            indegree[j] -= 1;
+            if indegree[j] == 0 {
+                ready.insert(j);
+            }
+        }
+    }
+    if pages.len() < n {
+        let left = (0..n)
+            .filter(|&i| indegree[i] > 0)
+            .map(|i| seq[i])
+            .collect();
+        return Err(OrderError::Cycle(left));
+    }
+    Ok(TopoOrder { pages, ambiguous })
+}
+
+// A rule `before|after` broken because `after` is printed first
+#[derive(Debug, PartialEq)]
+struct RuleViolation {
+    before: u16,
+    after: u16,
+    before_pos: usize,
+    after_pos: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct Move {
+    page: u16,
+    from: usize,
+    to: usize,
+}
+
+struct ViolationReport {
+    update: Vec<u16>,
+    violations: Vec<RuleViolation>,
+    // fewest pages to move, every other page keeps its relative order
+    moves: Vec<Move>,
+    fixed: Vec<u16>,
+    // the rules leave more than one order for the update's pages
+    ambiguous: bool,
+}
+
+fn rule_violations(seq: &[u16], rules: &RuleSet) -> Vec<RuleViolation> {
+    let mut violations = Vec::new();
+    for (after_pos, after) in seq.iter().enumerate() {
+        for (before_pos, before) in seq.iter().enumerate().skip(after_pos + 1) {
+            if before.comes_before(rules, *after) {
+                violations.push(RuleViolation {
+                    before: *before,
+                    after: *after,
+                    before_pos,
+                    after_pos,
+                });
+            }
+        }
+    }
+    violations
+}
+
+// reach[i][j]: the rules force seq[i] somewhere before seq[j], possibly through other pages
+fn reachability(seq: &[u16], rules: &RuleSet) -> Vec<Vec<bool>> {
+    let n = seq.len();
+    let mut reach = vec![vec![false; n]; n];
+    for (i, row) in reach.iter_mut().enumerate() {
+        let mut stack = vec![i];
+        while let Some(k) = stack.pop() {
+            for j in 0..n {
+                if !row[j] && seq[k].comes_before(rules, seq[j]) {
+                    row[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+    }
+    reach
+}
+
+// Pages that stay put are those with no forced order against their positions among each
+// other. The inverted pairs form a partial order, so by Dilworth the most pages that can stay
+// are n minus a maximum matching, and König's theorem tells which ones
+fn pages_to_keep(reach: &[Vec<bool>]) -> Vec<bool> {
+    let n = reach.len();
+    // i has to move in front of j although it is printed after it
+    let inverted = |i: usize, j: usize| j < i && reach[i][j];
+    let mut match_right: Vec<Option<usize>> = vec![None; n];
+    let mut match_left: Vec<Option<usize>> = vec![None; n];
+    fn augment(
+        i: usize,
+        n: usize,
+        inverted: &dyn Fn(usize, usize) -> bool,
+        seen: &mut [bool],
+        match_left: &mut [Option<usize>],
+        match_right: &mut [Option<usize>],
+    ) -> bool {
+        for j in 0..n {
+            if inverted(i, j) && !seen[j] {
+                seen[j] = true;
+                let free = match match_right[j] {
+                    None => true,
+                    Some(k) => augment(k, n, inverted, seen, match_left, match_right),
+                };
+                if free {
+                    match_right[j] = Some(i);
+                    match_left[i] = Some(j);
+                    return true;
+                }
+            }
         }
         false
     }
+    for i in 0..n {
+        let mut seen = vec![false; n];
+        augment(
+            i,
+            n,
+            &inverted,
+            &mut seen,
+            &mut match_left,
+            &mut match_right,
+        );
+    }
+    // alternating paths from unmatched left vertices
+    let mut visited_left = vec![false; n];
+    let mut visited_right = vec![false; n];
+    let mut stack: Vec<usize> = (0..n).filter(|&i| match_left[i].is_none()).collect();
+    for &i in stack.iter() {
+        visited_left[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        for j in 0..n {
+            if inverted(i, j) && !visited_right[j] && match_left[i] != Some(j) {
+                visited_right[j] = true;
+                if let Some(k) = match_right[j] {
+                    if !visited_left[k] {
+                        visited_left[k] = true;
+                        stack.push(k);
+                    }
+                }
+            }
+        }
+    }
+    // the minimum vertex cover is the unvisited left and the visited right vertices
+    (0..n)
+        .map(|i| visited_left[i] && !visited_right[i])
+        .collect()
 }
 
-fn sort_with_mapper(vec: &mut [u16], mapper: &HashMap<u16, Vec<u16>>) {
-    vec.sort_by(|a, b| {
-        if a.comes_before(mapper, *b) {
-            std::cmp::Ordering::Less
-        } else if b.comes_before(mapper, *a) {
-            std::cmp::Ordering::Greater
-        } else {
-            std::cmp::Ordering::Equal
+fn violation_report(seq: &[u16], rules: &RuleSet) -> Result<ViolationReport, OrderError> {
+    let n = seq.len();
+    let reach = reachability(seq, rules);
+    if (0..n).any(|i| reach[i][i]) {
+        return Err(topo_sort(seq, rules).unwrap_err());
+    }
+    let keep = pages_to_keep(&reach);
+    // rules plus the order of the kept pages
+    let mut successors: Vec<Vec<usize>> = (0..n)
+        .map(|i| (0..n).filter(|&j| reach[i][j]).collect())
+        .collect();
+    let kept: Vec<usize> = (0..n).filter(|&i| keep[i]).collect();
+    for pair in kept.windows(2) {
+        successors[pair[0]].push(pair[1]);
+    }
+    let fixed = kahn(seq, &successors)?.pages;
+    let moves = (0..n)
+        .filter(|&i| !keep[i])
+        .map(|i| Move {
+            page: seq[i],
+            from: i,
+            to: fixed.iter().position(|&p| p == seq[i]).unwrap(),
+        })
+        .collect();
+    Ok(ViolationReport {
+        update: seq.to_vec(),
+        violations: rule_violations(seq, rules),
+        moves,
+        fixed,
+        ambiguous: topo_sort(seq, rules)?.ambiguous,
+    })
+}
+
+impl ViolationReport {
+    fn to_text(&self) -> String {
+        let mut text = format!("update {:?}\n", self.update);
+        for v in self.violations.iter() {
+            text += &format!(
+                "  breaks {}|{}: {} at {} but {} at {}\n",
+                v.before, v.after, v.before, v.before_pos, v.after, v.after_pos
+            );
+        }
+        for m in self.moves.iter() {
+            text += &format!("  move {} from {} to {}\n", m.page, m.from, m.to);
         }
-    });
+        if self.ambiguous {
+            text += "  order is ambiguous\n";
+        }
+        text + &format!("  fixed {:?}", self.fixed)
+    }
+
+    fn to_json(&self) -> String {
+        let violations: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| {
+                format!(
+                    "{{\"rule\": \"{}|{}\", \"before_pos\": {}, \"after_pos\": {}}}",
+                    v.before, v.after, v.before_pos, v.after_pos
+                )
+            })
+            .collect();
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| {
+                format!(
+                    "{{\"page\": {}, \"from\": {}, \"to\": {}}}",
+                    m.page, m.from, m.to
+                )
+            })
+            .collect();
+        format!(
+            "{{\"update\": {:?}, \"violations\": [{}], \"moves\": [{}], \"fixed\": {:?}, \"ambiguous\": {}}}",
+            self.update,
+            violations.join(", "),
+            moves.join(", "),
+            self.fixed,
+            self.ambiguous
+        )
+    }
 }
 
 fn get_middle_element(vec: &[u16]) -> u16 {
-    if vec.len() % 2 == 0 {
+    if vec.len().is_multiple_of(2) {
         log::trace!("Even: {:?}", vec[vec.len() / 2]);
         vec[vec.len() / 2]
     } else {
@@ -53,6 +385,7 @@ fn get_middle_element(vec: &[u16]) -> u16 {
     }
 }
 
+// The original validator on the rule lists, kept to benchmark `RuleSet::is_valid` against
 fn check_sequence(seq: &[u16], mapper: &HashMap<u16, Vec<u16>>) -> bool {
     for i in (0..seq.len()).rev() {
         // get all the elements before the number
@@ -68,6 +401,32 @@ fn check_sequence(seq: &[u16], mapper: &HashMap<u16, Vec<u16>>) -> bool {
     true
 }
 
+fn bench_validation(rules: &[(u16, u16)], seqs: &[Vec<u16>], rounds: usize) {
+    let mut mapper: HashMap<u16, Vec<u16>> = HashMap::new();
+    for &(x, y) in rules {
+        mapper.entry(x).or_default().push(y);
+    }
+    let start = Instant::now();
+    let mut lists = 0;
+    for _ in 0..rounds {
+        lists = seqs.iter().filter(|s| check_sequence(s, &mapper)).count();
+    }
+    let lists_time = start.elapsed();
+
+    let start = Instant::now();
+    let rule_set = RuleSet::new(rules);
+    let mut bitset = 0;
+    for _ in 0..rounds {
+        bitset = seqs.iter().filter(|s| rule_set.is_valid(s)).count();
+    }
+    let bitset_time = start.elapsed();
+
+    assert_eq!(lists, bitset, "validators disagree");
+    info!("{} valid updates, {} rounds", bitset, rounds);
+    info!("rule lists: {:?}", lists_time);
+    info!("bitset:     {:?}", bitset_time);
+}
+
 fn main() {
     let start = Instant::now();
     env_logger::init();
@@ -85,8 +444,7 @@ fn main() {
     };
     debug!("File contents: {}", contents);
 
-    // lists all numbers that must be after a number
-    let mut mapper: HashMap<u16, Vec<u16>> = HashMap::new();
+    let mut rules: Vec<(u16, u16)> = Vec::new();
 
     // the sequences that need to be tested for validity
     let mut seqs: Vec<Vec<u16>> = Vec::new();
@@ -97,44 +455,71 @@ fn main() {
             break;
         }
         let nums: Vec<u16> = line.split('|').map(|x| x.parse().unwrap()).collect();
-        mapper.entry(nums[0]).or_default().push(nums[1]);
+        rules.push((nums[0], nums[1]));
     }
+    let rule_set = RuleSet::new(&rules);
 
     for line in lines {
         seqs.push(line.split(',').map(|x| x.parse().unwrap()).collect());
     }
-    debug!("Mapper: {:?}", mapper);
+    debug!("Rules: {:?}", rules);
     debug!("Sequences: {:?}", seqs);
 
+    // `--bench [rounds]` times the validation with the rule lists against the bitset
+    if let Some(i) = args.iter().position(|a| a == "--bench") {
+        let rounds = args.get(i + 1).and_then(|r| r.parse().ok()).unwrap_or(1000);
+        bench_validation(&rules, &seqs, rounds);
+        return;
+    }
+
+    // `--report` lists the broken rules and the fewest moves per invalid update and flags
+    // updates with more than one valid order, `--json` as JSON
+    let report = args.iter().any(|a| a == "--report");
+    let json = args.iter().any(|a| a == "--json");
+
     let mut result = 0;
     let mut res2 = 0;
-    for mut seq in seqs {
-        let res = check_sequence(&seq, &mapper);
-        let xx: Vec<u16> = seq.clone();
-        let sorted = xx.is_sorted_by(|a, b| {
-            if a.comes_before(&mapper, *b) {
-                true
-            } else if b.comes_before(&mapper, *a) {
-                false
-            } else {
-                true
-            }
-        });
-        assert_eq!(sorted, res);
+    let mut ambiguous = 0;
+    for seq in seqs {
+        let res = rule_set.is_valid(&seq);
 
         log::trace!("Sequence: {:?} is valid: {}", seq, res);
 
+        // valid updates are checked too, their order may not be the only one
+        let order = topo_sort(&seq, &rule_set);
+        let is_ambiguous = matches!(&order, Ok(o) if o.ambiguous);
+        if is_ambiguous {
+            ambiguous += 1;
+            log::warn!("Order of {:?} is ambiguous", seq);
+        }
+
+        if (!res || is_ambiguous) && (report || json) {
+            match violation_report(&seq, &rule_set) {
+                Ok(r) if json => println!("{}", r.to_json()),
+                Ok(r) => println!("{}", r.to_text()),
+                Err(e) => error!("Cannot order {:?}: {}", seq, e),
+            }
+        }
+
         if res {
             result += get_middle_element(&seq)
         } else {
             log::trace!("Before: {:?}", seq);
-            sort_with_mapper(&mut seq, &mapper);
-            log::trace!("Sorted: {:?}", seq);
-            res2 += get_middle_element(&seq);
+            match order {
+                Ok(order) => {
+                    log::trace!("Sorted: {:?}", order.pages);
+                    res2 += get_middle_element(&order.pages);
+                }
+                Err(e) => error!("Cannot order {:?}: {}", seq, e),
+            }
         }
     }
 
     log::info!("Result: {}", result);
     log::info!("Result: {}", res2);
+    log::info!("Ambiguous orders: {}", ambiguous);
     log::info!("time elapsed: {:?}", start.elapsed());
 }
+
+#[cfg(test)]
+mod test;
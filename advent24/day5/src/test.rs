@@ -0,0 +1,277 @@
+use super::*;
+
+const EXAMPLE: &str = "\
+47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47";
+
+fn example() -> (Vec<(u16, u16)>, Vec<Vec<u16>>) {
+    let (rules, updates) = EXAMPLE.split_once("\n\n").unwrap();
+    let rules = rules
+        .lines()
+        .map(|line| {
+            let (x, y) = line.split_once('|').unwrap();
+            (x.parse().unwrap(), y.parse().unwrap())
+        })
+        .collect();
+    let updates = updates
+        .lines()
+        .map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+        .collect();
+    (rules, updates)
+}
+
+// xorshift, enough to draw small rule sets
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn shuffle(&mut self, pages: &mut [u16]) {
+        for i in (1..pages.len()).rev() {
+            pages.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+fn permutations(pages: &[u16]) -> Vec<Vec<u16>> {
+    if pages.len() <= 1 {
+        return vec![pages.to_vec()];
+    }
+    let mut all = Vec::new();
+    for i in 0..pages.len() {
+        let mut rest = pages.to_vec();
+        let first = rest.remove(i);
+        for mut p in permutations(&rest) {
+            p.insert(0, first);
+            all.push(p);
+        }
+    }
+    all
+}
+
+fn common_subsequence(a: &[u16], b: &[u16]) -> usize {
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..a.len() {
+        for j in 0..b.len() {
+            table[i + 1][j + 1] = if a[i] == b[j] {
+                table[i][j] + 1
+            } else {
+                table[i][j + 1].max(table[i + 1][j])
+            };
+        }
+    }
+    table[a.len()][b.len()]
+}
+
+fn check_report(
+    rules: &RuleSet,
+    update: &[u16],
+    broken: &[(u16, u16)],
+    moves: usize,
+    fixed: &[u16],
+) {
+    let report = violation_report(update, rules).unwrap();
+    let pairs: Vec<(u16, u16)> = report
+        .violations
+        .iter()
+        .map(|v| (v.before, v.after))
+        .collect();
+    assert_eq!(pairs, broken, "{:?}", update);
+    assert_eq!(report.moves.len(), moves, "{:?}", update);
+    assert_eq!(report.fixed, fixed, "{:?}", update);
+}
+
+#[test]
+fn test_example_reports() {
+    let (rules, updates) = example();
+    let rules = RuleSet::new(&rules);
+    let invalid: Vec<&Vec<u16>> = updates.iter().filter(|u| !rules.is_valid(u)).collect();
+    assert_eq!(invalid.len(), 3);
+    check_report(&rules, invalid[0], &[(97, 75)], 1, &[97, 75, 47, 61, 53]);
+    check_report(&rules, invalid[1], &[(29, 13)], 1, &[61, 29, 13]);
+    check_report(
+        &rules,
+        invalid[2],
+        &[(75, 13), (29, 13), (47, 13), (47, 29)],
+        2,
+        &[97, 75, 47, 29, 13],
+    );
+}
+
+#[test]
+fn test_fewest_moves_brute_force() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..300 {
+        let n = 1 + rng.below(6);
+        // rules only point forward in a hidden order, so they never form a cycle
+        let mut hidden: Vec<u16> = (10..10 + n as u16).collect();
+        rng.shuffle(&mut hidden);
+        let mut rules = Vec::new();
+        for i in 0..n {
+            for j in i + 1..n {
+                if rng.below(3) == 0 {
+                    rules.push((hidden[i], hidden[j]));
+                }
+            }
+        }
+        let rule_set = RuleSet::new(&rules);
+        let mut update = hidden.clone();
+        rng.shuffle(&mut update);
+
+        let keep = pages_to_keep(&reachability(&update, &rule_set));
+        let kept = keep.iter().filter(|k| **k).count();
+        let best = permutations(&update)
+            .iter()
+            .filter(|p| rule_set.is_valid(p))
+            .map(|p| common_subsequence(&update, p))
+            .max()
+            .unwrap();
+        assert_eq!(kept, best, "rules {:?} update {:?}", rules, update);
+
+        let report = violation_report(&update, &rule_set).unwrap();
+        assert!(rule_set.is_valid(&report.fixed));
+        assert_eq!(report.moves.len(), n - best);
+        let kept_pages: Vec<u16> = (0..n).filter(|&i| keep[i]).map(|i| update[i]).collect();
+        let kept_in_fixed: Vec<u16> = report
+            .fixed
+            .iter()
+            .copied()
+            .filter(|p| kept_pages.contains(p))
+            .collect();
+        assert_eq!(kept_in_fixed, kept_pages);
+    }
+}
+
+#[test]
+fn test_topo_sort_example() {
+    let (rules, updates) = example();
+    let rules = RuleSet::new(&rules);
+    let fixed: Vec<Vec<u16>> = updates
+        .iter()
+        .filter(|u| !rules.is_valid(u))
+        .map(|u| topo_sort(u, &rules).unwrap())
+        .map(|order| {
+            assert!(!order.ambiguous);
+            order.pages
+        })
+        .collect();
+    assert_eq!(
+        fixed,
+        vec![
+            vec![97, 75, 47, 61, 53],
+            vec![61, 29, 13],
+            vec![97, 75, 47, 29, 13]
+        ]
+    );
+}
+
+#[test]
+fn test_topo_sort_cycle() {
+    let rules = RuleSet::new(&[(1, 2), (2, 3), (3, 1), (4, 1)]);
+    assert_eq!(
+        topo_sort(&[4, 3, 2, 1], &rules),
+        Err(OrderError::Cycle(vec![3, 2, 1]))
+    );
+    assert!(violation_report(&[1, 2, 3], &rules).is_err());
+}
+
+#[test]
+fn test_ambiguous_order() {
+    let rules = RuleSet::new(&[(1, 2), (2, 3)]);
+    // 4 has no rules, it can go anywhere
+    let order = topo_sort(&[2, 4, 1], &rules).unwrap();
+    assert_eq!(order.pages, vec![4, 1, 2]);
+    assert!(order.ambiguous);
+    // a valid update can still be ambiguous, 1|3 only follows through the missing 2
+    assert!(rules.is_valid(&[1, 3]));
+    assert!(topo_sort(&[1, 3], &rules).unwrap().ambiguous);
+    assert!(!topo_sort(&[1, 2, 3], &rules).unwrap().ambiguous);
+    assert!(violation_report(&[1, 4], &rules).unwrap().ambiguous);
+    assert!(!violation_report(&[3, 2, 1], &rules).unwrap().ambiguous);
+}
+
+fn rule_lists(rules: &[(u16, u16)]) -> HashMap<u16, Vec<u16>> {
+    let mut mapper: HashMap<u16, Vec<u16>> = HashMap::new();
+    for &(x, y) in rules {
+        mapper.entry(x).or_default().push(y);
+    }
+    mapper
+}
+
+#[test]
+fn test_bitset_matches_rule_lists() {
+    let (rules, updates) = example();
+    let rule_set = RuleSet::new(&rules);
+    let mapper = rule_lists(&rules);
+    let valid: Vec<bool> = updates.iter().map(|u| rule_set.is_valid(u)).collect();
+    assert_eq!(valid, vec![true, true, true, false, false, false]);
+    for update in updates.iter() {
+        assert_eq!(rule_set.is_valid(update), check_sequence(update, &mapper));
+    }
+
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    // large ids, ids without rules and more pages than fit in one word
+    let pool: Vec<u16> = (0..90).map(|i| i * 733).chain([65535, 1, 2]).collect();
+    for _ in 0..200 {
+        let rules: Vec<(u16, u16)> = (0..rng.below(300))
+            .map(|_| (pool[rng.below(80)], pool[rng.below(80)]))
+            .filter(|(x, y)| x != y)
+            .collect();
+        let rule_set = RuleSet::new(&rules);
+        let mapper = rule_lists(&rules);
+        for _ in 0..20 {
+            let update: Vec<u16> = (0..rng.below(8))
+                .map(|_| pool[rng.below(pool.len())])
+                .collect();
+            assert_eq!(
+                rule_set.is_valid(&update),
+                check_sequence(&update, &mapper),
+                "{:?}",
+                update
+            );
+        }
+    }
+}
+
+#[test]
+fn test_bitset_size_follows_page_count() {
+    let rule_set = RuleSet::new(&[(65535, 13), (13, 7)]);
+    assert_eq!(rule_set.stride, 1);
+    assert_eq!(rule_set.bits.len(), 3);
+    assert!(rule_set.contains(65535, 13));
+    assert!(!rule_set.contains(13, 65535));
+    assert!(!rule_set.contains(42, 13));
+    assert!(rule_set.is_valid(&[65535, 42, 13, 7]));
+    assert!(!rule_set.is_valid(&[13, 65535]));
+}
@@ -0,0 +1,267 @@
+//! Terminal animation of the guard walk, enabled with `--animate`
+//!
+//! Keys while it runs: `space` pauses and resumes, `n` steps one frame while paused,
+//! `+`/`-` change the speed and `q` stops the animation.
+use crate::{Direction, Position, State, WalkResult};
+use console::{Key, Term};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tiles {
+    Ascii,
+    Emoji,
+}
+
+impl FromStr for Tiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Tiles::Ascii),
+            "emoji" => Ok(Tiles::Emoji),
+            _ => Err(format!("unknown tile set '{}', expected ascii or emoji", s)),
+        }
+    }
+}
+
+impl Tiles {
+    // terminal columns taken by one cell
+    fn width(&self) -> usize {
+        match self {
+            Tiles::Ascii => 1,
+            Tiles::Emoji => 2,
+        }
+    }
+
+    fn guard(&self, direction: &Direction) -> &'static str {
+        match (self, direction) {
+            (Tiles::Emoji, _) => "👠",
+            (Tiles::Ascii, Direction::Up) => "^",
+            (Tiles::Ascii, Direction::Right) => ">",
+            (Tiles::Ascii, Direction::Down) => "v",
+            (Tiles::Ascii, Direction::Left) => "<",
+        }
+    }
+
+    fn walked(&self) -> &'static str {
+        match self {
+            Tiles::Ascii => "X",
+            Tiles::Emoji => "💣",
+        }
+    }
+
+    fn obstacle(&self) -> &'static str {
+        match self {
+            Tiles::Ascii => "#",
+            Tiles::Emoji => "🪨",
+        }
+    }
+
+    fn empty(&self) -> &'static str {
+        match self {
+            Tiles::Ascii => ".",
+            Tiles::Emoji => "..",
+        }
+    }
+}
+
+/// Rows and columns of the map shown around the guard
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl FromStr for Viewport {
+    type Err = String;
+
+    /// `40x80` is 40 rows of 80 cells
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid viewport '{}', expected <rows>x<cols>", s);
+        let (rows, cols) = s.split_once('x').ok_or_else(invalid)?;
+        let rows: usize = rows.parse().map_err(|_| invalid())?;
+        let cols: usize = cols.parse().map_err(|_| invalid())?;
+        if rows == 0 || cols == 0 {
+            return Err(invalid());
+        }
+        Ok(Viewport { rows, cols })
+    }
+}
+
+impl Viewport {
+    /// First row and column shown, keeps the guard centered but the view inside the map
+    fn origin(&self, guard: &[u16; 2], map_rows: usize, map_cols: usize) -> [usize; 2] {
+        let start = |pos: u16, size: usize, len: usize| {
+            (pos as usize)
+                .saturating_sub(size / 2)
+                .min(len.saturating_sub(size))
+        };
+        [
+            start(guard[0], self.rows, map_rows),
+            start(guard[1], self.cols, map_cols),
+        ]
+    }
+}
+
+pub struct Animation {
+    pub delay: Duration,
+    pub tiles: Tiles,
+    // the terminal size when not given
+    pub viewport: Option<Viewport>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            delay: Duration::from_millis(100),
+            tiles: Tiles::Emoji,
+            viewport: None,
+        }
+    }
+}
+
+enum Control {
+    TogglePause,
+    Step,
+    Faster,
+    Slower,
+    Quit,
+}
+
+// Reads keys on a separate thread so the animation keeps running between key presses.
+// The thread ends after `q` or after any key once `done` is set, so the terminal is not
+// left in raw mode
+fn spawn_key_reader(term: Term, done: Arc<AtomicBool>) -> mpsc::Receiver<Control> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let Ok(key) = term.read_key() else {
+            return;
+        };
+        let control = match key {
+            Key::Char(' ') => Control::TogglePause,
+            Key::Char('n') | Key::ArrowRight => Control::Step,
+            Key::Char('+') => Control::Faster,
+            Key::Char('-') => Control::Slower,
+            Key::Char('q') | Key::Escape => Control::Quit,
+            _ if done.load(Ordering::Relaxed) => Control::Quit,
+            _ => continue,
+        };
+        let quit = matches!(control, Control::Quit);
+        if sender.send(control).is_err() || quit || done.load(Ordering::Relaxed) {
+            return;
+        }
+    });
+    receiver
+}
+
+impl State {
+    /// One frame of the map cropped to the viewport, followed by a status line
+    pub fn render(&self, tiles: Tiles, viewport: Option<Viewport>) -> String {
+        let map_rows = self.field.len();
+        let map_cols = self.field.first().map_or(0, |r| r.len());
+        let view = viewport.unwrap_or(Viewport {
+            rows: map_rows,
+            cols: map_cols,
+        });
+        let [top, left] = view.origin(&self.cursor.cursor, map_rows, map_cols);
+        let mut frame = String::new();
+        for nrow in top..(top + view.rows).min(map_rows) {
+            for ncol in left..(left + view.cols).min(map_cols) {
+                let cell = [nrow as u16, ncol as u16];
+                frame += if self.cursor.cursor == cell {
+                    tiles.guard(&self.cursor.direction)
+                } else if self.is_walked(&cell) {
+                    tiles.walked()
+                } else if self.field[nrow][ncol] {
+                    tiles.obstacle()
+                } else {
+                    tiles.empty()
+                };
+            }
+            frame.push('\n');
+        }
+        frame += &format!(
+            "steps: {} visited: {} guard: {:?}",
+            self.walk_counter,
+            self.count_unique(),
+            self.cursor.cursor
+        );
+        frame
+    }
+
+    /// Walks like `walk`, drawing every step. Returns `None` when stopped with `q`
+    pub fn animate(&mut self, animation: &Animation) -> Option<WalkResult> {
+        let term = Term::stdout();
+        let interactive = term.is_term();
+        let viewport = animation.viewport.or_else(|| {
+            let (rows, cols) = term.size_checked()?;
+            Some(Viewport {
+                // room for the status and help lines
+                rows: (rows as usize).saturating_sub(3).max(1),
+                cols: (cols as usize / animation.tiles.width()).max(1),
+            })
+        });
+        let done = Arc::new(AtomicBool::new(false));
+        let keys = interactive.then(|| spawn_key_reader(term.clone(), done.clone()));
+        let mut delay = animation.delay;
+        let mut paused = false;
+        let mut lines = 0;
+        // the first frame shows the guard before it moves
+        let mut advance = false;
+        let result = loop {
+            let next = advance.then(|| self.walk_one());
+            let mut frame = self.render(animation.tiles, viewport);
+            if keys.is_some() {
+                frame += &format!(
+                    "\n{}[space] pause  [n] step  [+/-] speed ({} ms)  [q] quit",
+                    if paused { "PAUSED " } else { "" },
+                    delay.as_millis()
+                );
+            }
+            if interactive && lines > 0 {
+                let _ = term.clear_last_lines(lines);
+            }
+            lines = frame.lines().count();
+            let _ = term.write_line(&frame);
+            if next == Some(Position::OutOfBounds) {
+                break Some(WalkResult::HitWall);
+            }
+            if next.is_some() && self.is_in_loop() {
+                break Some(WalkResult::Loop);
+            }
+            advance = true;
+            let Some(keys) = &keys else {
+                thread::sleep(delay);
+                continue;
+            };
+            // while paused only a key moves the animation on
+            let control = if paused {
+                keys.recv().ok()
+            } else {
+                keys.recv_timeout(delay).ok()
+            };
+            match control {
+                Some(Control::TogglePause) => paused = !paused,
+                Some(Control::Step) => paused = true,
+                Some(Control::Faster) => delay /= 2,
+                Some(Control::Slower) => delay = (delay * 2).max(Duration::from_millis(1)),
+                Some(Control::Quit) => return None,
+                // the key reader is gone
+                None if paused => paused = false,
+                None => {}
+            }
+            // other keys redraw the paused frame instead of stepping
+            advance = !paused || matches!(control, Some(Control::Step));
+        };
+        if let Some(keys) = keys {
+            done.store(true, Ordering::Relaxed);
+            let _ = term.write_line("finished, press any key");
+            let _ = keys.recv();
+        }
+        result
+    }
+}
@@ -0,0 +1,317 @@
+//! Variants of the puzzle: every guard on the map walks in lockstep, guards can run into
+//! each other and blocked guards turn by a pluggable policy instead of always to the right
+use crate::{Cursor, Direction};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TurnPolicy {
+    Right,
+    Left,
+    Reverse,
+    // one of the three turns, drawn from a generator seeded with the value
+    Random(u64),
+}
+
+impl FromStr for TurnPolicy {
+    type Err = String;
+
+    /// `right`, `left`, `reverse` or `random[:<seed>]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "right" => Ok(TurnPolicy::Right),
+            None if s == "left" => Ok(TurnPolicy::Left),
+            None if s == "reverse" => Ok(TurnPolicy::Reverse),
+            None if s == "random" => Ok(TurnPolicy::Random(0)),
+            Some(("random", seed)) => seed
+                .parse()
+                .map(TurnPolicy::Random)
+                .map_err(|_| format!("invalid seed '{}'", seed)),
+            _ => Err(format!(
+                "unknown policy '{}', expected right, left, reverse or random:<seed>",
+                s
+            )),
+        }
+    }
+}
+
+/// What happens when guards meet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Collision {
+    // guards walk through each other
+    Pass,
+    // other guards are obstacles, of two guards heading for the same cell the first one goes
+    Block,
+    // guards that end up on the same cell or swap places stop there
+    Crash,
+}
+
+impl FromStr for Collision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pass" => Ok(Collision::Pass),
+            "block" => Ok(Collision::Block),
+            "crash" => Ok(Collision::Crash),
+            _ => Err(format!(
+                "unknown collision rule '{}', expected pass, block or crash",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GuardStatus {
+    Walking,
+    Left { tick: usize },
+    Crashed { tick: usize, with: usize },
+}
+
+pub struct Guard {
+    pub cursor: Cursor,
+    pub status: GuardStatus,
+    pub steps: usize,
+    // one flag per cell of the map
+    visited: Vec<bool>,
+}
+
+impl Guard {
+    pub fn count_unique(&self) -> usize {
+        self.visited.iter().filter(|v| **v).count()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    // every guard left the map or crashed
+    Stopped { tick: usize },
+    // the guards are back in a state seen `period` ticks ago
+    Loop { tick: usize, period: usize },
+    TickLimit { tick: usize },
+}
+
+enum Intent {
+    Leave,
+    Turn,
+    Move([u16; 2]),
+}
+
+pub struct Simulation<'a> {
+    field: &'a [Vec<bool>],
+    policy: TurnPolicy,
+    collision: Collision,
+    // splitmix64 state for the random policy
+    rng: u64,
+    pub guards: Vec<Guard>,
+    pub tick: usize,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(
+        field: &'a [Vec<bool>],
+        starts: &[Cursor],
+        policy: TurnPolicy,
+        collision: Collision,
+    ) -> Simulation<'a> {
+        let cols = field.first().map_or(0, |r| r.len());
+        let guards = starts
+            .iter()
+            .map(|start| {
+                let mut visited = vec![false; field.len() * cols];
+                visited[start.cursor[0] as usize * cols + start.cursor[1] as usize] = true;
+                Guard {
+                    cursor: start.clone(),
+                    status: GuardStatus::Walking,
+                    steps: 0,
+                    visited,
+                }
+            })
+            .collect();
+        let rng = match policy {
+            TurnPolicy::Random(seed) => seed,
+            _ => 0,
+        };
+        Simulation {
+            field,
+            policy,
+            collision,
+            rng,
+            guards,
+            tick: 0,
+        }
+    }
+
+    fn cols(&self) -> usize {
+        self.field.first().map_or(0, |r| r.len())
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn turn(&mut self, direction: &Direction) -> Direction {
+        let right_turns = match self.policy {
+            TurnPolicy::Right => 1,
+            TurnPolicy::Reverse => 2,
+            TurnPolicy::Left => 3,
+            TurnPolicy::Random(_) => 1 + self.next_random() % 3,
+        };
+        (0..right_turns).fold(direction.clone(), |d, _| d.turned_right())
+    }
+
+    // the cell in front of the guard, `None` at the edge of the map
+    fn ahead(&self, cursor: &Cursor) -> Option<[u16; 2]> {
+        let [row, col] = cursor.cursor;
+        let next = match cursor.direction {
+            Direction::Up => [row.checked_sub(1)?, col],
+            Direction::Down => [row + 1, col],
+            Direction::Left => [row, col.checked_sub(1)?],
+            Direction::Right => [row, col + 1],
+        };
+        let inside = (next[0] as usize) < self.field.len() && (next[1] as usize) < self.cols();
+        inside.then_some(next)
+    }
+
+    fn walking(&self) -> impl Iterator<Item = (usize, &Guard)> {
+        self.guards
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.status == GuardStatus::Walking)
+    }
+
+    /// Moves every walking guard once, all of them decide on the positions before the tick
+    pub fn step(&mut self) {
+        self.tick += 1;
+        let mut intents: Vec<Option<Intent>> = self
+            .guards
+            .iter()
+            .map(|guard| {
+                (guard.status == GuardStatus::Walking).then(|| match self.ahead(&guard.cursor) {
+                    None => Intent::Leave,
+                    Some(next) if self.field[next[0] as usize][next[1] as usize] => Intent::Turn,
+                    Some(next) => Intent::Move(next),
+                })
+            })
+            .collect();
+        if self.collision == Collision::Block {
+            let mut taken: Vec<[u16; 2]> = self.walking().map(|(_, g)| g.cursor.cursor).collect();
+            for intent in intents.iter_mut().flatten() {
+                if let Intent::Move(next) = intent {
+                    if taken.contains(next) {
+                        *intent = Intent::Turn;
+                    } else {
+                        taken.push(*next);
+                    }
+                }
+            }
+        }
+        let before: Vec<[u16; 2]> = self.guards.iter().map(|g| g.cursor.cursor).collect();
+        let cols = self.cols();
+        for (i, intent) in intents.into_iter().enumerate() {
+            match intent {
+                None => {}
+                Some(Intent::Leave) => {
+                    self.guards[i].status = GuardStatus::Left { tick: self.tick };
+                }
+                Some(Intent::Turn) => {
+                    let direction = self.guards[i].cursor.direction.clone();
+                    let direction = self.turn(&direction);
+                    self.guards[i].cursor.direction = direction;
+                }
+                Some(Intent::Move(next)) => {
+                    let guard = &mut self.guards[i];
+                    guard.cursor.cursor = next;
+                    guard.steps += 1;
+                    guard.visited[next[0] as usize * cols + next[1] as usize] = true;
+                }
+            }
+        }
+        if self.collision == Collision::Crash {
+            let walking: Vec<usize> = self.walking().map(|(i, _)| i).collect();
+            for (n, &a) in walking.iter().enumerate() {
+                for &b in walking[n + 1..].iter() {
+                    let (ga, gb) = (&self.guards[a].cursor.cursor, &self.guards[b].cursor.cursor);
+                    let swapped = *ga == before[b] && *gb == before[a];
+                    if ga == gb || swapped {
+                        let tick = self.tick;
+                        if self.guards[a].status == GuardStatus::Walking {
+                            self.guards[a].status = GuardStatus::Crashed { tick, with: b };
+                        }
+                        if self.guards[b].status == GuardStatus::Walking {
+                            self.guards[b].status = GuardStatus::Crashed { tick, with: a };
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Steps until no guard walks anymore, the guards repeat an earlier state or `max_ticks`.
+    /// The random policy never repeats a state, it only ends by leaving, crashing or `max_ticks`
+    pub fn run(&mut self, max_ticks: usize) -> Outcome {
+        // states of the walking guards by the tick they were seen
+        let mut seen: HashMap<Vec<Option<Cursor>>, usize> = HashMap::new();
+        let detect_loops = !matches!(self.policy, TurnPolicy::Random(_));
+        loop {
+            if self.walking().next().is_none() {
+                return Outcome::Stopped { tick: self.tick };
+            }
+            if detect_loops {
+                let state = self
+                    .guards
+                    .iter()
+                    .map(|g| (g.status == GuardStatus::Walking).then(|| g.cursor.clone()))
+                    .collect();
+                if let Some(first) = seen.insert(state, self.tick) {
+                    return Outcome::Loop {
+                        tick: self.tick,
+                        period: self.tick - first,
+                    };
+                }
+            }
+            if self.tick >= max_ticks {
+                return Outcome::TickLimit { tick: self.tick };
+            }
+            self.step();
+        }
+    }
+
+    /// Distinct cells visited by any of the guards
+    pub fn count_unique(&self) -> usize {
+        (0..self.field.len() * self.cols())
+            .filter(|&i| self.guards.iter().any(|g| g.visited[i]))
+            .count()
+    }
+}
+
+impl Display for Simulation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, guard) in self.guards.iter().enumerate() {
+            let status = match guard.status {
+                GuardStatus::Walking => "still walking".to_string(),
+                GuardStatus::Left { tick } => format!("left the map at tick {}", tick),
+                GuardStatus::Crashed { tick, with } => {
+                    format!("crashed into guard {} at tick {}", with, tick)
+                }
+            };
+            writeln!(
+                f,
+                "guard {} at {:?} going {:?}: {} steps, {} cells, {}",
+                i,
+                guard.cursor.cursor,
+                guard.cursor.direction,
+                guard.steps,
+                guard.count_unique(),
+                status
+            )?;
+        }
+        write!(f, "cells visited by any guard: {}", self.count_unique())
+    }
+}
@@ -1,10 +1,16 @@
 #![allow(dead_code)]
+use animate::{Animation, Tiles};
+use guards::{Collision, Simulation, TurnPolicy};
 use std::collections::HashSet;
 use std::env;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::Read;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+mod animate;
+mod guards;
+mod report;
 
 // ....#.....
 // .........#
@@ -17,7 +23,7 @@ use std::time::Instant;
 // #.........
 // ......#...
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 struct Cursor {
     cursor: [u16; 2],
     direction: Direction,
@@ -27,11 +33,59 @@ struct Cursor {
 struct State {
     field: Vec<Vec<bool>>,
     cursor: Cursor,
+    // every guard on the map, parts 1 and 2 follow the first one in `cursor`
+    guards: Vec<Cursor>,
     path: Vec<Cursor>,
+    visited: Visited,
+    // distinct cells in `path`
+    unique: usize,
     walk_counter: u32,
 }
 
+/// One bit per (row, col, direction) the guard has stood on, so a repeated state is found in O(1)
 #[derive(PartialEq, Clone)]
+struct Visited {
+    cols: usize,
+    bits: Vec<u64>,
+}
+
+impl Visited {
+    fn new(rows: usize, cols: usize) -> Visited {
+        Visited {
+            cols,
+            bits: vec![0; (rows * cols * 4).div_ceil(64)],
+        }
+    }
+
+    // the four directions of a cell share one nibble of a word
+    #[inline]
+    fn cell_index(&self, cell: &[u16; 2]) -> usize {
+        (cell[0] as usize * self.cols + cell[1] as usize) * 4
+    }
+
+    #[inline]
+    fn contains(&self, cursor: &Cursor) -> bool {
+        let i = self.cell_index(&cursor.cursor) + cursor.direction.index();
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    #[inline]
+    fn contains_cell(&self, cell: &[u16; 2]) -> bool {
+        let i = self.cell_index(cell);
+        self.bits[i / 64] & (0b1111 << (i % 64)) != 0
+    }
+
+    /// Marks the state, returns false if it was already visited
+    #[inline]
+    fn insert(&mut self, cursor: &Cursor) -> bool {
+        let i = self.cell_index(&cursor.cursor) + cursor.direction.index();
+        let new = self.bits[i / 64] & (1 << (i % 64)) == 0;
+        self.bits[i / 64] |= 1 << (i % 64);
+        new
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 enum Direction {
     Up,
     Down,
@@ -39,6 +93,47 @@ enum Direction {
     Right,
 }
 
+impl Direction {
+    fn from_char(c: char) -> Option<Direction> {
+        match c {
+            '^' => Some(Direction::Up),
+            '>' => Some(Direction::Right),
+            'v' => Some(Direction::Down),
+            '<' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Right => '>',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+        }
+    }
+
+    #[inline]
+    fn turned_right(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+            Direction::Right => Direction::Down,
+        }
+    }
+
+    #[inline]
+    fn index(&self) -> usize {
+        match self {
+            Direction::Up => 0,
+            Direction::Right => 1,
+            Direction::Down => 2,
+            Direction::Left => 3,
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum Position {
     Blocked,
@@ -56,36 +151,50 @@ impl State {
     fn read_file(file_name: &str) -> std::io::Result<State> {
         let contents = read_file(file_name)?;
         let mut field = Vec::new();
-        let mut cursor: Cursor = Cursor {
-            cursor: [0, 0],
-            direction: Direction::Up,
-        };
+        let mut guards = Vec::new();
         for (nrow, line) in contents.lines().enumerate() {
             let mut row = Vec::new();
             for (col, c) in line.chars().enumerate() {
                 row.push(c == '#');
-                if c == '^' {
-                    cursor.cursor = [nrow as u16, col as u16];
+                if let Some(direction) = Direction::from_char(c) {
+                    guards.push(Cursor {
+                        cursor: [nrow as u16, col as u16],
+                        direction,
+                    });
                 }
             }
             field.push(row);
         }
+        let cursor = guards.first().cloned().unwrap_or(Cursor {
+            cursor: [0, 0],
+            direction: Direction::Up,
+        });
+        let mut visited = Visited::new(field.len(), field.first().map_or(0, |r| r.len()));
+        visited.insert(&cursor);
         Ok(State {
             field,
             cursor: cursor.clone(),
+            guards,
             path: vec![cursor],
+            visited,
+            unique: 1,
             walk_counter: 0,
         })
     }
 
+    // records the current cursor before the guard moves on
     #[inline]
-    fn turn_right(&mut self) {
-        match self.cursor.direction {
-            Direction::Up => self.cursor.direction = Direction::Right,
-            Direction::Down => self.cursor.direction = Direction::Left,
-            Direction::Left => self.cursor.direction = Direction::Up,
-            Direction::Right => self.cursor.direction = Direction::Down,
+    fn visit(&mut self) {
+        if !self.visited.contains_cell(&self.cursor.cursor) {
+            self.unique += 1;
         }
+        self.visited.insert(&self.cursor);
+        self.path.push(self.cursor.clone());
+    }
+
+    #[inline]
+    fn turn_right(&mut self) {
+        self.cursor.direction = self.cursor.direction.turned_right();
     }
 
     fn get_position(&self, cursor: &[u16; 2]) -> Position {
@@ -127,10 +236,10 @@ impl State {
             }
             Position::OutOfBounds => {
                 self.walk_counter += 1;
-                self.path.push(self.cursor.clone());
+                self.visit();
             }
             Position::Cursor(cur) => {
-                self.path.push(self.cursor.clone());
+                self.visit();
                 self.walk_counter += 1;
                 self.cursor.cursor = cur;
             }
@@ -139,50 +248,24 @@ impl State {
     }
 
     fn at_start(&self) -> bool {
-        self.cursor == self.path[0] && self.cursor.direction == Direction::Up
-    }
-
-    fn remove_row_lines(&self) {
-        let rows = self.field.len();
-        // remove so many lines from the terminal output (move up)
-        print!("\x1b[{}A", rows + 1);
-    }
-
-    fn print_empty_lines(&self) {
-        let rows = self.field.len();
-        for _ in 0..rows {
-            println!();
-        }
+        self.cursor == self.path[0]
     }
 
     fn count_unique(&self) -> usize {
-        self.path
-            .iter()
-            .map(|x| x.cursor)
-            .collect::<std::collections::HashSet<_>>()
-            .len()
+        self.unique
     }
 
     #[inline]
     fn is_walked(&self, cursor: &[u16; 2]) -> bool {
-        self.path.iter().any(|x| x.cursor == *cursor)
+        self.visited.contains_cell(cursor)
     }
 
     #[inline]
     fn is_in_loop(&self) -> bool {
-        self.path.contains(&self.cursor)
-    }
-
-    fn walk(&mut self, display: bool) -> WalkResult {
-        // get log_level
-        // let logit: bool = matches!(
-        //     env::var("RUST_LOG").unwrap_or("info".to_string()).as_str(),
-        //     "debug" | "trace"
-        // );
-        // if logit {
-        //     log::info!("Starting walk");
-        //     self.print_empty_lines();
-        // }
+        self.visited.contains(&self.cursor)
+    }
+
+    fn walk(&mut self) -> WalkResult {
         loop {
             let next = self.walk_one();
             if next == Position::OutOfBounds {
@@ -191,36 +274,129 @@ impl State {
             if self.is_in_loop() {
                 return WalkResult::Loop;
             }
-            if display {
-                self.remove_row_lines();
-                // wait for half a second
-                std::thread::sleep(std::time::Duration::from_millis(100));
-                println!("{}", self);
-            }
         }
     }
 }
 
 impl Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        for (nrow, row) in self.field.iter().enumerate() {
-            for (ncol, cell) in row.iter().enumerate() {
-                if self.cursor.cursor == [nrow as u16, ncol as u16] {
-                    write!(f, "👠")?;
-                } else if self.is_walked(&[nrow as u16, ncol as u16]) {
-                    write!(f, "💣")?;
-                } else if *cell {
-                    write!(f, "🪨")?;
-                } else {
-                    write!(f, "..")?;
-                }
+        writeln!(f, "{}", self.render(Tiles::Emoji, None))
+    }
+}
+
+/// For every cell and direction the last free cell before the next obstacle, `None` when the
+/// guard walks off the map instead. Lets a walk jump from turn to turn
+struct JumpTable {
+    cols: usize,
+    stops: Vec<Option<[u16; 2]>>,
+}
+
+impl JumpTable {
+    fn new(field: &[Vec<bool>]) -> JumpTable {
+        let rows = field.len();
+        let cols = field.first().map_or(0, |r| r.len());
+        let mut table = JumpTable {
+            cols,
+            stops: vec![None; rows * cols * 4],
+        };
+        let blocked = |r: usize, c: usize| field[r][c];
+        // every sweep starts at the edge the guard is heading to, so the next cell is known
+        for c in 0..cols {
+            for r in 0..rows {
+                let up = match r {
+                    0 => None,
+                    _ if blocked(r - 1, c) => Some([r as u16, c as u16]),
+                    _ => table.stops[table.index(r - 1, c, Direction::Up)],
+                };
+                let i = table.index(r, c, Direction::Up);
+                table.stops[i] = up;
+            }
+            for r in (0..rows).rev() {
+                let down = match r + 1 {
+                    next if next == rows => None,
+                    next if blocked(next, c) => Some([r as u16, c as u16]),
+                    next => table.stops[table.index(next, c, Direction::Down)],
+                };
+                let i = table.index(r, c, Direction::Down);
+                table.stops[i] = down;
+            }
+        }
+        for r in 0..rows {
+            for c in 0..cols {
+                let left = match c {
+                    0 => None,
+                    _ if blocked(r, c - 1) => Some([r as u16, c as u16]),
+                    _ => table.stops[table.index(r, c - 1, Direction::Left)],
+                };
+                let i = table.index(r, c, Direction::Left);
+                table.stops[i] = left;
+            }
+            for c in (0..cols).rev() {
+                let right = match c + 1 {
+                    next if next == cols => None,
+                    next if blocked(r, next) => Some([r as u16, c as u16]),
+                    next => table.stops[table.index(r, next, Direction::Right)],
+                };
+                let i = table.index(r, c, Direction::Right);
+                table.stops[i] = right;
             }
-            if nrow == self.field.len() - 1 {
-                write!(f, " {} {}", self.walk_counter, self.count_unique())?;
+        }
+        table
+    }
+
+    #[inline]
+    fn index(&self, row: usize, col: usize, direction: Direction) -> usize {
+        (row * self.cols + col) * 4 + direction.index()
+    }
+
+    /// Where the guard turns next, with `obstacle` placed on the map in addition to the table
+    #[inline]
+    fn next_stop(&self, cursor: &Cursor, obstacle: [u16; 2]) -> Option<[u16; 2]> {
+        let [row, col] = cursor.cursor;
+        let stop = self.stops[self.index(row as usize, col as usize, cursor.direction.clone())];
+        // the obstacle only matters if it is ahead and not behind the next stop
+        let ahead = match cursor.direction {
+            Direction::Up => obstacle[1] == col && obstacle[0] < row,
+            Direction::Down => obstacle[1] == col && obstacle[0] > row,
+            Direction::Left => obstacle[0] == row && obstacle[1] < col,
+            Direction::Right => obstacle[0] == row && obstacle[1] > col,
+        };
+        if !ahead {
+            return stop;
+        }
+        let before = match cursor.direction {
+            Direction::Up => [obstacle[0] + 1, col],
+            Direction::Down => [obstacle[0] - 1, col],
+            Direction::Left => [row, obstacle[1] + 1],
+            Direction::Right => [row, obstacle[1] - 1],
+        };
+        let closer = match (stop, &cursor.direction) {
+            (None, _) => true,
+            (Some(s), Direction::Up) => before[0] >= s[0],
+            (Some(s), Direction::Down) => before[0] <= s[0],
+            (Some(s), Direction::Left) => before[1] >= s[1],
+            (Some(s), Direction::Right) => before[1] <= s[1],
+        };
+        if closer {
+            Some(before)
+        } else {
+            stop
+        }
+    }
+
+    /// Walks from turn to turn, only the states right after a turn are tracked in `visited`
+    fn walk(&self, start: &Cursor, obstacle: [u16; 2], visited: &mut Visited) -> WalkResult {
+        let mut cursor = start.clone();
+        while let Some(stop) = self.next_stop(&cursor, obstacle) {
+            cursor = Cursor {
+                cursor: stop,
+                direction: cursor.direction.turned_right(),
+            };
+            if !visited.insert(&cursor) {
+                return WalkResult::Loop;
             }
-            writeln!(f)?;
         }
-        Ok(())
+        WalkResult::HitWall
     }
 }
 
@@ -237,9 +413,14 @@ pub fn read_file(file_name: &str) -> std::io::Result<String> {
 }
 
 fn check_for_loops(s: State) -> u16 {
+    loop_obstacles(&s).len() as u16
+}
+
+/// Cells on the guard's path where one more obstacle traps it in a loop, sorted
+fn loop_obstacles(s: &State) -> Vec<[u16; 2]> {
     use rayon::prelude::*;
     let mut work = s.clone();
-    work.walk(false);
+    work.walk();
     let mut options: Vec<[u16; 2]> = work
         .path
         .iter()
@@ -251,24 +432,23 @@ fn check_for_loops(s: State) -> u16 {
     let options: Vec<_> = unique_options.into_iter().collect();
     log::debug!("{:?}", options);
 
+    let jumps = JumpTable::new(&s.field);
     let pb = indicatif::ProgressBar::new(options.len() as u64);
-    let count = options
+    let mut obstacles: Vec<[u16; 2]> = options
         .par_iter()
-        .map(|option| {
-            let mut work = s.clone();
-            work.field[option[0] as usize][option[1] as usize] = true;
-            let res = work.walk(false);
+        .filter(|option| {
+            let mut visited = Visited::new(s.field.len(), s.field[0].len());
+            let res = jumps.walk(&s.cursor, **option, &mut visited);
             log::trace!("{:?}", res);
             pb.inc(1);
-            match res {
-                WalkResult::Loop => 1,
-                WalkResult::HitWall => 0,
-            }
+            matches!(res, WalkResult::Loop)
         })
-        .sum();
+        .copied()
+        .collect();
 
     pb.finish_with_message("done");
-    count
+    obstacles.sort();
+    obstacles
 }
 
 fn main() {
@@ -282,11 +462,146 @@ fn main() {
     let s = State::read_file(&args[1]).unwrap();
     // log::info!("state: {}", s);
 
+    // `--animate` draws the walk, tuned with `--delay <ms>`, `--tiles ascii|emoji` and
+    // `--viewport <rows>x<cols>` (defaults to the terminal size)
+    // `--loops` shows the obstacles of part 2 on the map, `--loops-json <file>` exports them
+    let mut animate = false;
+    let mut animation = Animation::default();
+    let mut show_loops = false;
+    let mut loops_json = None;
+    // maps with several guards, `--policy` or `--collision` also run all guards together,
+    // see the guards module
+    let mut simulate = s.guards.len() > 1;
+    let mut policy = TurnPolicy::Right;
+    let mut collision = Collision::Pass;
+    let mut max_ticks = 1_000_000;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        let parsed = match arg.as_str() {
+            "--animate" => {
+                animate = true;
+                Ok(())
+            }
+            "--delay" => rest
+                .next()
+                .and_then(|ms| ms.parse().ok())
+                .map(|ms| animation.delay = Duration::from_millis(ms))
+                .ok_or_else(|| "--delay expects milliseconds".to_string()),
+            "--tiles" => rest
+                .next()
+                .map_or(Err("--tiles expects ascii or emoji".to_string()), |t| {
+                    t.parse()
+                })
+                .map(|tiles| animation.tiles = tiles),
+            "--viewport" => rest
+                .next()
+                .map_or(Err("--viewport expects <rows>x<cols>".to_string()), |v| {
+                    v.parse()
+                })
+                .map(|viewport| animation.viewport = Some(viewport)),
+            "--loops" => {
+                show_loops = true;
+                Ok(())
+            }
+            "--loops-json" => rest
+                .next()
+                .map(|file| loops_json = Some(file))
+                .ok_or_else(|| "--loops-json expects a file name".to_string()),
+            "--guards" => {
+                simulate = true;
+                Ok(())
+            }
+            "--policy" => rest
+                .next()
+                .map_or(Err("--policy expects a turning policy".to_string()), |p| {
+                    p.parse()
+                })
+                .map(|p| {
+                    policy = p;
+                    simulate = true;
+                }),
+            "--collision" => rest
+                .next()
+                .map_or(
+                    Err("--collision expects pass, block or crash".to_string()),
+                    |c| c.parse(),
+                )
+                .map(|c| {
+                    collision = c;
+                    simulate = true;
+                }),
+            "--max-ticks" => rest
+                .next()
+                .and_then(|t| t.parse().ok())
+                .map(|t| max_ticks = t)
+                .ok_or_else(|| "--max-ticks expects a number".to_string()),
+            other => Err(format!("unknown argument '{}'", other)),
+        };
+        if let Err(e) = parsed {
+            log::error!("{}", e);
+            return;
+        }
+    }
+
+    if simulate {
+        let mut simulation = Simulation::new(&s.field, &s.guards, policy, collision);
+        let outcome = simulation.run(max_ticks);
+        println!(
+            "{} guards, {:?}, {:?}: {:?}",
+            s.guards.len(),
+            policy,
+            collision,
+            outcome
+        );
+        println!("{}", simulation);
+        if s.guards.len() > 1 {
+            log::warn!("Parts 1 and 2 follow the first guard only");
+        }
+    }
+
     let mut work = s.clone();
-    work.walk(false);
+    if animate {
+        match work.animate(&animation) {
+            Some(result) => log::info!("{:?}", result),
+            None => return,
+        }
+    } else {
+        work.walk();
+    }
     println!("Part 1: {}", work.count_unique());
 
-    println!("Part 2: {}", check_for_loops(s));
+    let part2 = Instant::now();
+    if !show_loops && loops_json.is_none() {
+        println!("Part 2: {}", check_for_loops(s));
+        log::info!("check_for_loops: {:?}", part2.elapsed());
+        log::info!("time elapsed: {:?}", start.elapsed());
+        return;
+    }
+    let candidates: Vec<_> = loop_obstacles(&s)
+        .into_iter()
+        .map(|obstacle| {
+            // the jump table found the loop, the cell by cell walk has to find it too
+            report::describe_loop(&s, obstacle)
+                .unwrap_or_else(|| panic!("no loop with an obstacle at {:?}", obstacle))
+        })
+        .collect();
+    println!("Part 2: {}", candidates.len());
+    log::info!("loop_obstacles: {:?}", part2.elapsed());
+    if show_loops {
+        println!("{}", report::render_loops(&s, &candidates));
+        for c in candidates.iter() {
+            println!(
+                "obstacle {:?}: loop of {} moves entered at {:?} going {:?}",
+                c.obstacle, c.length, c.entry.cursor, c.entry.direction
+            );
+        }
+    }
+    if let Some(file) = loops_json {
+        match report::write_json(file, &candidates) {
+            Ok(()) => log::info!("Loops written to {}", file),
+            Err(e) => log::error!("Could not write {}: {}", file, e),
+        }
+    }
 
     log::info!("time elapsed: {:?}", start.elapsed());
 }
@@ -0,0 +1,89 @@
+//! Details on the obstacles that trap the guard, for `--loops` and `--loops-json`
+use crate::{Cursor, State, WalkResult};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// An obstacle that makes the guard walk in circles
+pub struct LoopCandidate {
+    pub obstacle: [u16; 2],
+    // moves needed to come back to `entry`
+    pub length: usize,
+    // first state of the walk that is part of the loop
+    pub entry: Cursor,
+}
+
+/// Walks cell by cell with the obstacle in place, `None` if the guard gets out after all
+pub fn describe_loop(s: &State, obstacle: [u16; 2]) -> Option<LoopCandidate> {
+    let mut work = s.clone();
+    work.field[obstacle[0] as usize][obstacle[1] as usize] = true;
+    match work.walk() {
+        WalkResult::HitWall => None,
+        WalkResult::Loop => {
+            // the path holds every state the guard moved on from, the repeated one opens the loop
+            let start = work.path.iter().position(|c| *c == work.cursor)?;
+            Some(LoopCandidate {
+                obstacle,
+                length: work.path.len() - start,
+                entry: work.cursor,
+            })
+        }
+    }
+}
+
+impl LoopCandidate {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"obstacle\": [{}, {}], \"loop_length\": {}, \"entry\": [{}, {}], \"direction\": \"{}\"}}",
+            self.obstacle[0],
+            self.obstacle[1],
+            self.length,
+            self.entry.cursor[0],
+            self.entry.cursor[1],
+            format!("{:?}", self.entry.direction).to_lowercase()
+        )
+    }
+}
+
+pub fn write_json(file_name: &str, candidates: &[LoopCandidate]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(file_name)?);
+    writeln!(out, "[")?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        let separator = if i + 1 < candidates.len() { "," } else { "" };
+        writeln!(out, "  {}{}", candidate.to_json(), separator)?;
+    }
+    writeln!(out, "]")?;
+    out.flush()
+}
+
+/// The map with the guard's path and start, `O` for every candidate obstacle and `+` where its
+/// loop starts
+pub fn render_loops(s: &State, candidates: &[LoopCandidate]) -> String {
+    let mut work = s.clone();
+    work.walk();
+    let mut map: Vec<Vec<char>> = s
+        .field
+        .iter()
+        .enumerate()
+        .map(|(nrow, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(ncol, blocked)| match blocked {
+                    true => '#',
+                    false if work.is_walked(&[nrow as u16, ncol as u16]) => 'X',
+                    false => '.',
+                })
+                .collect()
+        })
+        .collect();
+    for c in candidates {
+        map[c.entry.cursor[0] as usize][c.entry.cursor[1] as usize] = '+';
+    }
+    for c in candidates {
+        map[c.obstacle[0] as usize][c.obstacle[1] as usize] = 'O';
+    }
+    map[s.cursor.cursor[0] as usize][s.cursor.cursor[1] as usize] = s.cursor.direction.to_char();
+    map.iter()
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
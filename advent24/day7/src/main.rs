@@ -17,103 +17,201 @@ impl Display for Equation {
     }
 }
 
-impl Equation {
-    fn new(x: u64, a: Vec<u64>) -> Self {
-        Equation { x, a }
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Add,
+    Mul,
+    Concat,
+}
+
+impl Operator {
+    const PART1: [Operator; 2] = [Operator::Add, Operator::Mul];
+    const PART2: [Operator; 3] = [Operator::Add, Operator::Mul, Operator::Concat];
+
+    fn apply(&self, left: u64, right: u64) -> Option<u64> {
+        match self {
+            Operator::Add => left.checked_add(right),
+            Operator::Mul => left.checked_mul(right),
+            Operator::Concat => left
+                .checked_mul(10u64.pow(digits(right)))?
+                .checked_add(right),
+        }
     }
 
-    fn check_valid(&mut self) -> bool {
-        let last = self.a.pop().unwrap();
-        if self.a.is_empty() {
-            self.x == last
-        } else if self.x % last == 0 {
-            let mut clone = self.clone();
-            let newx = clone.x.checked_sub(last);
-            let valid = match newx {
-                Some(value) => {
-                    clone.x = value;
-                    clone.check_valid()
-                }
-                None => return false,
-            };
-            self.x /= last;
-            self.check_valid() || valid
-        } else {
-            if self.x < last {
-                return false;
+    /// The left operand that gives `result` with `right`, inverts `apply`
+    fn unapply(&self, result: u64, right: u64) -> Option<u64> {
+        match self {
+            Operator::Add => result.checked_sub(right),
+            Operator::Mul if right == 0 => None,
+            Operator::Mul => result.is_multiple_of(right).then(|| result / right),
+            Operator::Concat => {
+                let shift = 10u64.pow(digits(right));
+                (result % shift == right).then(|| result / shift)
             }
-            self.x -= last;
-            self.check_valid()
         }
     }
+}
 
-    fn check_subs(&mut self) -> bool {
-        if self.a.is_empty() {
-            return false;
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Concat => write!(f, "||"),
         }
-        let last = self.a.pop().unwrap();
-        let newx = self.x.checked_sub(last);
-        match newx {
-            Some(value) => {
-                self.x = value;
-                self.check_validn()
-            }
-            None => false,
+    }
+}
+
+fn digits(n: u64) -> u32 {
+    n.checked_ilog10().unwrap_or(0) + 1
+}
+
+/// Operands with the operators between them, evaluated left to right
+#[derive(Debug, Clone, PartialEq)]
+struct Expression {
+    operands: Vec<u64>,
+    operators: Vec<Operator>,
+}
+
+impl Expression {
+    fn evaluate(&self) -> Option<u64> {
+        self.operators
+            .iter()
+            .zip(self.operands[1..].iter())
+            .try_fold(self.operands[0], |acc, (op, n)| op.apply(acc, *n))
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.operands[0])?;
+        for (op, n) in self.operators.iter().zip(self.operands[1..].iter()) {
+            write!(f, " {} {}", op, n)?;
         }
+        Ok(())
+    }
+}
+
+impl Equation {
+    fn new(x: u64, a: Vec<u64>) -> Self {
+        Equation { x, a }
+    }
+
+    /// One operator assignment that makes the equation true
+    fn solve(&self, operators: &[Operator]) -> Option<Expression> {
+        let mut found = Vec::new();
+        self.search(
+            self.x,
+            self.a.len(),
+            operators,
+            &mut Vec::new(),
+            &mut found,
+            false,
+        );
+        found.pop()
     }
 
-    fn check_div(&mut self) -> bool {
-        if self.a.is_empty() {
+    /// Every operator assignment that makes the equation true
+    fn solve_all(&self, operators: &[Operator]) -> Vec<Expression> {
+        let mut found = Vec::new();
+        self.search(
+            self.x,
+            self.a.len(),
+            operators,
+            &mut Vec::new(),
+            &mut found,
+            true,
+        );
+        found
+    }
+
+    // Works backwards from the target: the last operand is undone with every operator that
+    // allows it, `suffix` holds the chosen operators from the back. Returns true to stop
+    fn search(
+        &self,
+        x: u64,
+        len: usize,
+        operators: &[Operator],
+        suffix: &mut Vec<Operator>,
+        found: &mut Vec<Expression>,
+        all: bool,
+    ) -> bool {
+        if len == 0 {
             return false;
         }
-        let last = self.a.pop().unwrap();
-        if self.x % last == 0 {
-            self.x /= last;
-            self.check_validn()
-        } else {
-            false
+        if len == 1 {
+            if x == self.a[0] {
+                found.push(Expression {
+                    operands: self.a.clone(),
+                    operators: suffix.iter().rev().copied().collect(),
+                });
+                return !all;
+            }
+            return false;
         }
+        let last = self.a[len - 1];
+        for op in operators {
+            if *op == Operator::Mul && last == 0 && x == 0 {
+                // anything times zero, every assignment of the operands before works
+                if self.any_prefix(len - 1, operators, suffix, op, found, all) {
+                    return true;
+                }
+                continue;
+            }
+            if let Some(rest) = op.unapply(x, last) {
+                suffix.push(*op);
+                let stop = self.search(rest, len - 1, operators, suffix, found, all);
+                suffix.pop();
+                if stop {
+                    return true;
+                }
+            }
+        }
+        false
     }
 
-    fn check_poplastdig(&mut self) -> bool {
-        if self.a.is_empty() {
-            return false;
-        }
-        let last = self.a.pop().unwrap();
-        let mut ss: String = format!("{}", self.x);
-        let ll: String = format!("{}", last);
-        if ss.ends_with(&ll) {
-            // remove last digits
-            for _ in 0..ll.len() {
-                ss.pop();
+    // adds the assignments of the first `len` operands followed by `op` and `suffix`,
+    // skipping prefixes that overflow before they reach the zero
+    fn any_prefix(
+        &self,
+        len: usize,
+        operators: &[Operator],
+        suffix: &[Operator],
+        op: &Operator,
+        found: &mut Vec<Expression>,
+        all: bool,
+    ) -> bool {
+        let mut prefix = vec![0; len - 1];
+        loop {
+            let mut chosen: Vec<Operator> = prefix.iter().map(|&i| operators[i]).collect();
+            chosen.push(*op);
+            chosen.extend(suffix.iter().rev());
+            let expression = Expression {
+                operands: self.a.clone(),
+                operators: chosen,
+            };
+            if expression.evaluate().is_some() {
+                found.push(expression);
+                if !all {
+                    return true;
+                }
             }
-            if let Ok(value) = ss.parse() {
-                self.x = value;
-            } else {
+            // next assignment, counting in base `operators.len()`
+            let Some(i) = prefix.iter().rposition(|&i| i + 1 < operators.len()) else {
                 return false;
-            }
-            self.check_validn()
-        } else {
-            false
+            };
+            prefix[i] += 1;
+            prefix[i + 1..].fill(0);
         }
     }
 
+    fn check_valid(&self) -> bool {
+        self.solve(&Operator::PART1).is_some()
+    }
+
     /// Checks, if it is possible to make the equation valid by combining elements
-    fn check_validn(&mut self) -> bool {
-        let last = *self.a.last().unwrap();
-        if self.a.len() == 1 {
-            return self.x == last;
-        }
-        if self.clone().check_subs() {
-            return true;
-        }
-        if self.clone().check_div() {
-            return true;
-        }
-        if self.clone().check_poplastdig() {
-            return true;
-        }
-        false
+    fn check_validn(&self) -> bool {
+        self.solve(&Operator::PART2).is_some()
     }
 }
 
@@ -152,7 +250,7 @@ fn main() {
     let start = Instant::now();
     env_logger::init();
     let args: Vec<String> = env::args().collect();
-    let equations = if args.len() < 2 {
+    let equations = if args.len() < 2 || args[1].starts_with("--") {
         // log::error!("Please provide a filename as a command line argument.");
         vec![Equation::from("7290: 6 8 6 15")]
     } else {
@@ -160,41 +258,46 @@ fn main() {
     };
     log::debug!("equations: {:?}", equations);
 
-    use rayon::prelude::*;
-    let res: u64 = equations
-        .clone()
-        .into_par_iter()
-        .filter_map(|mut eq| {
-            log::debug!("{}", eq);
-            let orig = eq.x;
-            let valid = eq.check_valid();
-            if valid {
-                log::debug!("valid: {}", valid);
-                Some(orig)
-            } else {
-                None
-            }
-        })
-        .sum();
-
-    log::info!("result: {}", res);
-
-    let res: u64 = equations
-        .into_par_iter()
-        .filter_map(|mut eq| {
-            log::debug!("{}", eq);
-            let orig = eq.x;
-            let valid = eq.check_validn();
-            if valid {
-                log::debug!("valid: {}", valid);
-                Some(orig)
-            } else {
-                None
-            }
-        })
-        .sum();
+    // `--explain` prints the operators of every true equation, `--all` every assignment
+    let explain = args.iter().any(|a| a == "--explain");
+    let all = args.iter().any(|a| a == "--all");
 
-    log::info!("result 2: {}", res);
+    for (part, operators) in [(1, &Operator::PART1[..]), (2, &Operator::PART2[..])] {
+        use rayon::prelude::*;
+        let solved: Vec<(u64, Vec<Expression>)> = equations
+            .par_iter()
+            .filter_map(|eq| {
+                log::debug!("{}", eq);
+                let mut expressions = if all {
+                    eq.solve_all(operators)
+                } else {
+                    eq.solve(operators).into_iter().collect()
+                };
+                // an assignment that does not check out does not count towards the result
+                expressions.retain(|expression| {
+                    let valid = expression.evaluate() == Some(eq.x);
+                    if !valid {
+                        log::error!("{} does not evaluate to {}, skipped", expression, eq.x);
+                    }
+                    valid
+                });
+                (!expressions.is_empty()).then_some((eq.x, expressions))
+            })
+            .collect();
+        if explain || all {
+            for (x, expressions) in solved.iter() {
+                for expression in expressions {
+                    println!("part {}: {} = {}", part, x, expression);
+                }
+            }
+        }
+        let res: u64 = solved.iter().map(|(x, _)| x).sum();
+        if part == 1 {
+            log::info!("result: {}", res);
+        } else {
+            log::info!("result 2: {}", res);
+        }
+    }
 
     log::info!("time elapsed: {:?}", start.elapsed());
 }